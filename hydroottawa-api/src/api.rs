@@ -1,10 +1,16 @@
 use chrono::NaiveDate;
-use reqwest::Client;
+use futures::{StreamExt, stream};
+use reqwest::{Client, RequestBuilder, StatusCode};
 use serde::Serialize;
+use std::sync::{Mutex, PoisonError};
 
 use crate::{
-    auth::HoAuth,
-    error::Result,
+    auth::{HoAuth, HoSession},
+    client::HoClient,
+    drift::SchemaDrift,
+    error::{Error, Result},
+    recording::Recording,
+    retry::RetryPolicy,
     types::{HoHourlyUsage, HoProfile},
 };
 
@@ -14,73 +20,190 @@ struct HourlyRequest {
 }
 
 pub struct HoApi {
-    client: Client,
-    debug_responses: bool,
+    client: HoClient,
+    recording: Recording,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+    drift: Mutex<SchemaDrift>,
 }
 
-const HO_API_URI: &str = "https://api-myaccount.hydroottawa.com";
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+impl Default for HoApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl HoApi {
     #[must_use]
-    pub fn new(debug_responses: bool) -> Self {
-        let client = Client::new();
+    pub fn new() -> Self {
+        Self::with_client(HoClient::default())
+    }
 
+    #[must_use]
+    pub fn with_client(client: HoClient) -> Self {
         Self {
             client,
-            debug_responses,
+            recording: Recording::Off,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            retry: RetryPolicy::default(),
+            drift: Mutex::default(),
         }
     }
 
-    pub async fn profile(&self, auth: &HoAuth) -> Result<HoProfile> {
-        let url = format!("{HO_API_URI}/profile");
-
-        let profile_dict = self
-            .client
-            .get(url)
-            .header("Accept", "application/json")
-            .header("x-id", &auth.id_token)
-            .header("x-access", &auth.access_token)
-            .bearer_auth(&auth.jwt_token)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+    /// Maximum number of requests `hourly_range` keeps in flight
+    #[must_use]
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Record the raw exchanges to fixture files, or replay them
+    #[must_use]
+    pub fn with_recording(mut self, recording: Recording) -> Self {
+        self.recording = recording;
+        self
+    }
+
+    /// Retries of transient failures, each request gets the full budget
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Fields that appeared or disappeared in the responses so far
+    pub fn drift(&self) -> SchemaDrift {
+        self.drift
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 
-        if self.debug_responses {
-            dbg!(&profile_dict);
+    fn record_drift(&self, drift: SchemaDrift) {
+        self.drift
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .merge(drift);
+    }
+
+    // Sends an authenticated request, retrying transient failures. Every API
+    // call is a read, so they are all safe to repeat.
+    async fn send<F>(&self, auth: &HoAuth, request: F) -> Result<serde_json::Value>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let mut retry = 0;
+
+        loop {
+            match self.send_once(auth, &request).await {
+                Err(e) if e.is_transient() && retry < self.retry.max_retries => {
+                    tokio::time::sleep(self.retry.delay(retry, e.retry_after())).await;
+                    retry = retry.saturating_add(1);
+                }
+                result => return result,
+            }
         }
+    }
+
+    // Sends an authenticated request, refreshing the tokens before they
+    // expire or once if the API rejects them
+    async fn send_once<F>(&self, auth: &HoAuth, request: &F) -> Result<serde_json::Value>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let authorized = |session: &HoSession| {
+            request(&self.client.http)
+                .header("Accept", "application/json")
+                .header("x-id", session.id_token.expose())
+                .header("x-access", session.access_token.expose())
+                .bearer_auth(session.jwt_token.expose())
+        };
 
+        // Replayed sessions never reach Cognito
+        let replay = self.recording.is_replay();
+        if !replay {
+            auth.refresh_if_expiring().await?;
+        }
+
+        let session = auth.session().await;
+        let send = |session: &HoSession| {
+            self.recording
+                .execute(&self.client.http, authorized(session))
+        };
+        let mut response = send(&session).await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED && !replay {
+            auth.refresh_rejected(session.jwt_token.expose()).await?;
+
+            let session = auth.session().await;
+            response = send(&session).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::from_api(response).await);
+        }
+
+        let dict = response.json::<serde_json::Value>().await?;
+
+        Ok(dict)
+    }
+
+    pub async fn profile(&self, auth: &HoAuth) -> Result<HoProfile> {
+        let url = self.client.api_url("/profile");
+
+        let profile_dict = self.send(auth, |client| client.get(&url)).await?;
+
+        self.record_drift(SchemaDrift::profile(&profile_dict));
         let profile: HoProfile = serde_json::from_value(profile_dict)?;
 
         Ok(profile)
     }
 
     pub async fn hourly(&self, auth: &HoAuth, date: &NaiveDate) -> Result<HoHourlyUsage> {
-        let url = format!("{HO_API_URI}/usage/consumption/hourly");
+        let url = self.client.api_url("/usage/consumption/hourly");
 
         let day = HourlyRequest {
             date: date.format("%Y-%m-%d").to_string(),
         };
 
         let hourly_dict = self
-            .client
-            .post(url)
-            .header("Accept", "application/json")
-            .header("x-id", &auth.id_token)
-            .header("x-access", &auth.access_token)
-            .bearer_auth(&auth.jwt_token)
-            .json(&day)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
+            .send(auth, |client| client.post(&url).json(&day))
             .await?;
 
-        if self.debug_responses {
-            dbg!(&hourly_dict);
-        }
-
+        self.record_drift(SchemaDrift::hourly_usage(&hourly_dict));
         let usage: HoHourlyUsage = serde_json::from_value(hourly_dict)?;
 
         Ok(usage)
     }
+
+    /// Hourly usage for every day from `start` to `end` inclusive, in date
+    /// order, each day with its own result so one failure doesn't lose the rest
+    pub async fn hourly_range(
+        &self,
+        auth: &HoAuth,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> Vec<(NaiveDate, Result<HoHourlyUsage>)> {
+        let days = start.iter_days().take_while(|day| day <= end);
+
+        self.hourly_days(auth, days).await
+    }
+
+    /// Hourly usage for each of `days`, see `hourly_range`
+    pub async fn hourly_days<I>(
+        &self,
+        auth: &HoAuth,
+        days: I,
+    ) -> Vec<(NaiveDate, Result<HoHourlyUsage>)>
+    where
+        I: IntoIterator<Item = NaiveDate>,
+    {
+        stream::iter(days)
+            .map(|day| async move { (day, self.hourly(auth, &day).await) })
+            .buffered(self.max_concurrency)
+            .collect()
+            .await
+    }
 }
@@ -1,45 +1,95 @@
-use aws_cognito_srp::{SrpClient, User};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use aws_cognito_srp::{SrpClient, TrackedDevice, UntrackedDevice, User};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use serde::{
+    Deserialize, Serialize,
+    de::{DeserializeOwned, IgnoredAny},
+};
 use std::collections::HashMap;
+use tokio::sync::RwLock;
 
-use crate::error::{Error, Result};
+use crate::{
+    claims::SessionClaims,
+    client::HoClient,
+    error::{Error, Result},
+    secret::Secret,
+};
+
+/// Tokens issued by Cognito and the Hydro Ottawa `/app-token` exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoSession {
+    pub jwt_token: Secret,
+    pub id_token: Secret,
+    pub access_token: Secret,
+    pub refresh_token: Secret,
+    pub expires_at: DateTime<Utc>,
+    /// Device Cognito remembered at the last login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<HoDevice>,
+}
+
+/// Device remembered by Cognito, which stands in for MFA on later logins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoDevice {
+    pub key: String,
+    pub group_key: String,
+    pub password: Secret,
+}
+
+impl HoSession {
+    /// Empty tokens that never expire, for replaying recordings, which are
+    /// never authenticated or refreshed
+    #[must_use]
+    pub fn replayed() -> Self {
+        Self {
+            jwt_token: Secret::default(),
+            id_token: Secret::default(),
+            access_token: Secret::default(),
+            refresh_token: Secret::default(),
+            expires_at: DateTime::<Utc>::MAX_UTC,
+            device: None,
+        }
+    }
+
+    /// True when the tokens expire within the refresh margin
+    #[must_use]
+    pub fn is_expiring(&self) -> bool {
+        Utc::now()
+            .checked_add_signed(REFRESH_MARGIN)
+            .is_none_or(|deadline| deadline >= self.expires_at)
+    }
+}
 
 pub struct HoAuth {
-    pub jwt_token: String,
-    pub id_token: String,
-    pub access_token: String,
+    client: HoClient,
+    session: RwLock<HoSession>,
 }
 
-const HO_API_URI: &str = "https://api-myaccount.hydroottawa.com";
-const COGNITO_ENDPOINT: &str = "https://cognito-idp.ca-central-1.amazonaws.com/";
-const CLIENT_ID: &str = "7scfcis6ecucktmp4aqi1jk6cb";
-const USER_POOL_ID: &str = "ca-central-1_VYnwOhMBK";
+// Refresh a little before Cognito's expiry so in-flight requests don't race it
+const REFRESH_MARGIN: TimeDelta = TimeDelta::minutes(5);
+
+// Name shown for remembered devices in the user pool
+const DEVICE_NAME: &str = "hydroottawa";
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct InitiateAuthRequest {
     auth_flow: String,
     client_id: String,
-    auth_parameters: HashMap<String, String>,
+    auth_parameters: HashMap<String, Secret>,
     client_metadata: HashMap<String, String>,
 }
 
+// Cognito's answer to InitiateAuth and RespondToAuthChallenge, either the
+// tokens or the next challenge
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct InitiateAuthResponse {
-    //challenge_name: String,
-    challenge_parameters: ChallengeParameters,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-struct ChallengeParameters {
-    salt: String,
-    secret_block: String,
-    srp_b: String,
-    username: String,
-    user_id_for_srp: String,
+struct ChallengeResponse {
+    authentication_result: Option<AuthenticationResult>,
+    challenge_name: Option<String>,
+    session: Option<Secret>,
+    #[serde(default)]
+    challenge_parameters: HashMap<String, String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -47,139 +97,810 @@ struct ChallengeParameters {
 struct RespondToAuthChallengeRequest {
     challenge_name: String,
     client_id: String,
-    challenge_responses: HashMap<String, String>,
+    challenge_responses: HashMap<String, Secret>,
     client_metadata: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session: Option<Secret>,
+}
+
+// Challenges of the Cognito auth flow
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ChallengeName {
+    PasswordVerifier,
+    DeviceSrpAuth,
+    DevicePasswordVerifier,
+    SmsMfa,
+    SoftwareTokenMfa,
+    NewPasswordRequired,
+    Other(String),
+}
+
+impl From<&str> for ChallengeName {
+    fn from(name: &str) -> Self {
+        match name {
+            "PASSWORD_VERIFIER" => Self::PasswordVerifier,
+            "DEVICE_SRP_AUTH" => Self::DeviceSrpAuth,
+            "DEVICE_PASSWORD_VERIFIER" => Self::DevicePasswordVerifier,
+            "SMS_MFA" => Self::SmsMfa,
+            "SOFTWARE_TOKEN_MFA" => Self::SoftwareTokenMfa,
+            "NEW_PASSWORD_REQUIRED" => Self::NewPasswordRequired,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl ChallengeName {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::PasswordVerifier => "PASSWORD_VERIFIER",
+            Self::DeviceSrpAuth => "DEVICE_SRP_AUTH",
+            Self::DevicePasswordVerifier => "DEVICE_PASSWORD_VERIFIER",
+            Self::SmsMfa => "SMS_MFA",
+            Self::SoftwareTokenMfa => "SOFTWARE_TOKEN_MFA",
+            Self::NewPasswordRequired => "NEW_PASSWORD_REQUIRED",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+/// Second factor Cognito asks for after the password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MfaKind {
+    /// Code sent by text message
+    Sms,
+    /// Code from an authenticator app
+    SoftwareToken,
+}
+
+impl MfaKind {
+    fn challenge_name(self) -> ChallengeName {
+        match self {
+            Self::Sms => ChallengeName::SmsMfa,
+            Self::SoftwareToken => ChallengeName::SoftwareTokenMfa,
+        }
+    }
+
+    fn code_key(self) -> &'static str {
+        match self {
+            Self::Sms => "SMS_MFA_CODE",
+            Self::SoftwareToken => "SOFTWARE_TOKEN_MFA_CODE",
+        }
+    }
+}
+
+/// MFA code request handed to the `login_with_mfa` callback
+#[derive(Debug, Clone)]
+pub struct MfaChallenge {
+    pub kind: MfaKind,
+    /// Masked phone number the SMS code was sent to
+    pub destination: Option<String>,
+}
+
+/// Challenge the caller has to answer before Cognito hands out tokens
+#[derive(Debug, Clone)]
+pub enum Challenge {
+    Mfa(MfaChallenge),
+    /// The account still has the temporary password it was created with
+    NewPasswordRequired {
+        /// Attributes to set along with the new password, e.g. `name`
+        required_attributes: Vec<String>,
+    },
+}
+
+impl Challenge {
+    fn name(&self) -> ChallengeName {
+        match self {
+            Self::Mfa(mfa) => mfa.kind.challenge_name(),
+            Self::NewPasswordRequired { .. } => ChallengeName::NewPasswordRequired,
+        }
+    }
+
+    // `None` for challenges answered internally or not supported at all
+    fn from_response(name: &ChallengeName, parameters: &HashMap<String, String>) -> Option<Self> {
+        let challenge = match name {
+            ChallengeName::SmsMfa | ChallengeName::SoftwareTokenMfa => Self::Mfa(MfaChallenge {
+                kind: if *name == ChallengeName::SmsMfa {
+                    MfaKind::Sms
+                } else {
+                    MfaKind::SoftwareToken
+                },
+                destination: parameters.get("CODE_DELIVERY_DESTINATION").cloned(),
+            }),
+            ChallengeName::NewPasswordRequired => {
+                // A JSON encoded list of `userAttributes.<name>`
+                let required: Vec<String> = parameters
+                    .get("requiredAttributes")
+                    .and_then(|attributes| serde_json::from_str(attributes).ok())
+                    .unwrap_or_default();
+
+                Self::NewPasswordRequired {
+                    required_attributes: required
+                        .iter()
+                        .map(|attribute| {
+                            attribute
+                                .strip_prefix(USER_ATTRIBUTES_PREFIX)
+                                .unwrap_or(attribute)
+                                .to_string()
+                        })
+                        .collect(),
+                }
+            }
+            _ => return None,
+        };
+
+        Some(challenge)
+    }
+}
+
+const USER_ATTRIBUTES_PREFIX: &str = "userAttributes.";
+
+/// Answer to a `Challenge`
+#[derive(Debug, Clone)]
+pub enum ChallengeAnswer {
+    MfaCode(Secret),
+    NewPassword {
+        password: Secret,
+        /// Values for the `required_attributes` of the challenge
+        attributes: HashMap<String, String>,
+    },
+}
+
+/// Outcome of a login step
+pub enum LoginStep {
+    Authenticated(HoAuth),
+    ChallengeRequired(PendingChallenge),
+}
+
+/// Login paused on a challenge, continued with `respond`
+pub struct PendingChallenge {
+    client: HoClient,
+    username: String,
+    session: Option<Secret>,
+    challenge: Challenge,
+    device: Option<HoDevice>,
+}
+
+impl PendingChallenge {
+    #[must_use]
+    pub fn challenge(&self) -> &Challenge {
+        &self.challenge
+    }
+
+    /// Send `answer`, which may lead to another challenge
+    pub async fn respond(self, answer: ChallengeAnswer) -> Result<LoginStep> {
+        let name = self.challenge.name();
+
+        let mut challenge_responses = match (&self.challenge, answer) {
+            (Challenge::Mfa(mfa), ChallengeAnswer::MfaCode(code)) => {
+                HashMap::from([(mfa.kind.code_key().to_string(), code)])
+            }
+            (
+                Challenge::NewPasswordRequired { .. },
+                ChallengeAnswer::NewPassword {
+                    password,
+                    attributes,
+                },
+            ) => attributes
+                .into_iter()
+                .map(|(name, value)| (format!("{USER_ATTRIBUTES_PREFIX}{name}"), value.into()))
+                .chain([("NEW_PASSWORD".to_string(), password)])
+                .collect(),
+            _ => return Err(Error::InvalidChallengeAnswer(name.as_str().to_string())),
+        };
+        challenge_responses.insert("USERNAME".to_string(), self.username.as_str().into());
+
+        let request = RespondToAuthChallengeRequest {
+            challenge_name: name.as_str().to_string(),
+            client_id: self.client.endpoints.client_id.clone(),
+            challenge_responses,
+            client_metadata: HashMap::new(),
+            session: self.session,
+        };
+
+        let response = cognito_request(&self.client, "RespondToAuthChallenge", &request).await?;
+        let login = Login {
+            client: &self.client,
+            srp_client: None,
+            device: self.device,
+        };
+        login.advance(self.username, response).await
+    }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct AuthenticationResult {
-    access_token: String,
-    //expires_in: u32,
-    id_token: String,
-    //refresh_token: String,
+    access_token: Secret,
+    expires_in: i64,
+    id_token: Secret,
+    // Not returned by REFRESH_TOKEN_AUTH, the original one stays valid
+    refresh_token: Option<Secret>,
     //token_type: String,
+    // Only when the user pool remembers devices and this login used none
+    new_device_metadata: Option<NewDeviceMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct NewDeviceMetadata {
+    device_key: String,
+    device_group_key: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConfirmDeviceRequest {
+    access_token: Secret,
+    device_key: String,
+    device_name: String,
+    device_secret_verifier_config: DeviceSecretVerifierConfig,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeviceSecretVerifierConfig {
+    password_verifier: String,
+    salt: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConfirmDeviceResponse {
+    #[serde(default)]
+    user_confirmation_necessary: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct UpdateDeviceStatusRequest {
+    access_token: Secret,
+    device_key: String,
+    device_remembered_status: String,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct RespondToAuthChallengeResponse {
+struct AuthResultResponse {
     authentication_result: AuthenticationResult,
 }
 
+async fn cognito_request<Req, Resp>(client: &HoClient, target: &str, request: &Req) -> Result<Resp>
+where
+    Req: Serialize,
+    Resp: DeserializeOwned,
+{
+    let response = client
+        .http
+        .post(&client.endpoints.cognito_endpoint)
+        .header("Content-Type", "application/x-amz-json-1.1")
+        .header(
+            "X-Amz-Target",
+            format!("AWSCognitoIdentityProviderService.{target}"),
+        )
+        .json(request)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(Error::from_cognito(response).await);
+    }
+
+    Ok(response.json::<Resp>().await?)
+}
+
+// Cognito reports the token lifetime in seconds from now
+fn expires_at(expires_in: i64) -> DateTime<Utc> {
+    let now = Utc::now();
+
+    TimeDelta::try_seconds(expires_in)
+        .and_then(|lifetime| now.checked_add_signed(lifetime))
+        .unwrap_or(now)
+}
+
+// Exchange Cognito tokens for the Hydro Ottawa JWT
+async fn app_token(client: &HoClient, id_token: &Secret, access_token: &Secret) -> Result<Secret> {
+    let app_token_url = client.api_url("/app-token");
+    let response = client
+        .http
+        .get(&app_token_url)
+        .header("Accept", "application/json")
+        .header("x-id", id_token.expose())
+        .header("x-access", access_token.expose())
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(Error::from_api(response).await);
+    }
+
+    // Extract the custom JWT from the response header
+    let jwt_token = response
+        .headers()
+        .get("x-amzn-remapped-authorization")
+        .ok_or_else(|| Error::MissingHeader("x-amzn-remapped-authorization".to_string()))?
+        .to_str()?
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| Error::InvalidTokenFormat("Token doesn't start with 'Bearer '".to_string()))?
+        .into();
+
+    Ok(jwt_token)
+}
+
+// Challenge parameter `name`, an error when Cognito left it out
+fn parameter<'a>(parameters: &'a HashMap<String, String>, name: &str) -> Result<&'a str> {
+    parameters
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| Error::MissingChallengeParameter(name.to_string()))
+}
+
+// Answers PASSWORD_VERIFIER with the SRP proof
+fn password_verifier(
+    client: &HoClient,
+    srp_client: &SrpClient<User>,
+    parameters: &HashMap<String, String>,
+    session: Option<Secret>,
+) -> Result<RespondToAuthChallengeRequest> {
+    let verification = srp_client.verify(
+        parameter(parameters, "SECRET_BLOCK")?,
+        parameter(parameters, "USER_ID_FOR_SRP")?,
+        parameter(parameters, "SALT")?,
+        parameter(parameters, "SRP_B")?,
+    )?;
+
+    let mut challenge_responses = HashMap::new();
+    challenge_responses.insert(
+        "USERNAME".to_string(),
+        parameter(parameters, "USERNAME")?.into(),
+    );
+    challenge_responses.insert(
+        "PASSWORD_CLAIM_SECRET_BLOCK".to_string(),
+        verification.password_claim_secret_block.into(),
+    );
+    challenge_responses.insert("TIMESTAMP".to_string(), verification.timestamp.into());
+    challenge_responses.insert(
+        "PASSWORD_CLAIM_SIGNATURE".to_string(),
+        verification.password_claim_signature.into(),
+    );
+
+    Ok(RespondToAuthChallengeRequest {
+        challenge_name: ChallengeName::PasswordVerifier.as_str().to_string(),
+        client_id: client.endpoints.client_id.clone(),
+        challenge_responses,
+        client_metadata: HashMap::new(),
+        session,
+    })
+}
+
+// Answers DEVICE_SRP_AUTH with the SRP public value of the remembered device
+fn device_srp_auth(
+    client: &HoClient,
+    srp_client: &SrpClient<TrackedDevice>,
+    username: &str,
+    session: Option<Secret>,
+) -> RespondToAuthChallengeRequest {
+    let auth_params = srp_client.get_auth_parameters();
+
+    let mut challenge_responses = HashMap::new();
+    challenge_responses.insert("USERNAME".to_string(), username.into());
+    challenge_responses.insert("DEVICE_KEY".to_string(), auth_params.device_key.into());
+    challenge_responses.insert("SRP_A".to_string(), auth_params.a.into());
+
+    RespondToAuthChallengeRequest {
+        challenge_name: ChallengeName::DeviceSrpAuth.as_str().to_string(),
+        client_id: client.endpoints.client_id.clone(),
+        challenge_responses,
+        client_metadata: HashMap::new(),
+        session,
+    }
+}
+
+// Answers DEVICE_PASSWORD_VERIFIER with the SRP proof of the device password
+fn device_password_verifier(
+    client: &HoClient,
+    srp_client: &SrpClient<TrackedDevice>,
+    username: &str,
+    parameters: &HashMap<String, String>,
+    session: Option<Secret>,
+) -> Result<RespondToAuthChallengeRequest> {
+    let verification = srp_client.verify(
+        parameter(parameters, "SECRET_BLOCK")?,
+        parameter(parameters, "SALT")?,
+        parameter(parameters, "SRP_B")?,
+    )?;
+
+    let mut challenge_responses = HashMap::new();
+    challenge_responses.insert("USERNAME".to_string(), username.into());
+    challenge_responses.insert(
+        "DEVICE_KEY".to_string(),
+        srp_client.get_auth_parameters().device_key.into(),
+    );
+    challenge_responses.insert(
+        "PASSWORD_CLAIM_SECRET_BLOCK".to_string(),
+        verification.password_claim_secret_block.into(),
+    );
+    challenge_responses.insert("TIMESTAMP".to_string(), verification.timestamp.into());
+    challenge_responses.insert(
+        "PASSWORD_CLAIM_SIGNATURE".to_string(),
+        verification.password_claim_signature.into(),
+    );
+
+    Ok(RespondToAuthChallengeRequest {
+        challenge_name: ChallengeName::DevicePasswordVerifier.as_str().to_string(),
+        client_id: client.endpoints.client_id.clone(),
+        challenge_responses,
+        client_metadata: HashMap::new(),
+        session,
+    })
+}
+
+// Registers the device Cognito offered at the end of a login, so the next
+// login can answer DEVICE_SRP_AUTH instead of MFA
+async fn confirm_device(
+    client: &HoClient,
+    access_token: &Secret,
+    metadata: NewDeviceMetadata,
+) -> Result<HoDevice> {
+    let device = UntrackedDevice::new(
+        &client.endpoints.user_pool_id,
+        &metadata.device_group_key,
+        &metadata.device_key,
+    );
+    let verifier =
+        SrpClient::new(device, &client.endpoints.client_id, None).get_password_verifier();
+
+    let confirm_request = ConfirmDeviceRequest {
+        access_token: access_token.clone(),
+        device_key: metadata.device_key.clone(),
+        device_name: DEVICE_NAME.to_string(),
+        device_secret_verifier_config: DeviceSecretVerifierConfig {
+            password_verifier: verifier.verifier,
+            salt: verifier.salt,
+        },
+    };
+    let response: ConfirmDeviceResponse =
+        cognito_request(client, "ConfirmDevice", &confirm_request).await?;
+
+    // Pools that leave remembering to the user only track the device so far
+    if response.user_confirmation_necessary {
+        let status_request = UpdateDeviceStatusRequest {
+            access_token: access_token.clone(),
+            device_key: metadata.device_key.clone(),
+            device_remembered_status: "remembered".to_string(),
+        };
+        cognito_request::<_, IgnoredAny>(client, "UpdateDeviceStatus", &status_request).await?;
+    }
+
+    Ok(HoDevice {
+        key: metadata.device_key,
+        group_key: metadata.device_group_key,
+        password: verifier.password.into(),
+    })
+}
+
+// A login in progress, besides Cognito's latest response
+struct Login<'a> {
+    client: &'a HoClient,
+    // Answers PASSWORD_VERIFIER, which only comes once at the start
+    srp_client: Option<&'a SrpClient<User>>,
+    // Answers DEVICE_SRP_AUTH, kept in the session unless Cognito offers a
+    // new one
+    device: Option<HoDevice>,
+}
+
+impl Login<'_> {
+    // Drives the challenge state machine from `response` until Cognito hands
+    // out the tokens or asks for something only the caller can answer
+    async fn advance(
+        mut self,
+        mut username: String,
+        mut response: ChallengeResponse,
+    ) -> Result<LoginStep> {
+        let mut device_srp_client = None;
+
+        loop {
+            if let Some(auth_result) = response.authentication_result {
+                let auth = HoAuth::authenticated(self.client, auth_result, self.device).await?;
+                return Ok(LoginStep::Authenticated(auth));
+            }
+
+            let name = response
+                .challenge_name
+                .as_deref()
+                .filter(|name| !name.is_empty())
+                .map(ChallengeName::from)
+                .ok_or(Error::MissingChallenge)?;
+            let parameters = response.challenge_parameters;
+
+            // Later challenges expect the username Cognito resolved, not the alias
+            if let Some(resolved) = parameters.get("USERNAME") {
+                username.clone_from(resolved);
+            }
+
+            let request = if name == ChallengeName::PasswordVerifier
+                && let Some(srp_client) = self.srp_client.take()
+            {
+                password_verifier(self.client, srp_client, &parameters, response.session)?
+            } else if name == ChallengeName::DeviceSrpAuth {
+                // Only issued when InitiateAuth named a remembered device
+                let device = self.device.as_ref().ok_or(Error::DeviceRequired)?;
+                let srp_client = SrpClient::new(
+                    TrackedDevice::new(
+                        &self.client.endpoints.user_pool_id,
+                        &device.group_key,
+                        &device.key,
+                        device.password.expose(),
+                    ),
+                    &self.client.endpoints.client_id,
+                    None,
+                );
+                let request =
+                    device_srp_auth(self.client, &srp_client, &username, response.session);
+                device_srp_client = Some(srp_client);
+                request
+            } else if name == ChallengeName::DevicePasswordVerifier
+                && let Some(srp_client) = device_srp_client.take()
+            {
+                device_password_verifier(
+                    self.client,
+                    &srp_client,
+                    &username,
+                    &parameters,
+                    response.session,
+                )?
+            } else {
+                let challenge = Challenge::from_response(&name, &parameters)
+                    .ok_or_else(|| Error::UnsupportedChallenge(name.as_str().to_string()))?;
+
+                return Ok(LoginStep::ChallengeRequired(PendingChallenge {
+                    client: self.client.clone(),
+                    username,
+                    session: response.session,
+                    challenge,
+                    device: self.device,
+                }));
+            };
+
+            response = cognito_request(self.client, "RespondToAuthChallenge", &request).await?;
+        }
+    }
+}
+
 impl HoAuth {
     pub async fn new<U, P>(username: U, password: P) -> Result<Self>
     where
         U: AsRef<str>,
         P: AsRef<str>,
     {
-        let client = Client::new();
+        Self::login(&HoClient::default(), username, password).await
+    }
+
+    /// Log in with the SRP flow through `client`, failing if the account has
+    /// MFA enabled
+    pub async fn login<U, P>(client: &HoClient, username: U, password: P) -> Result<Self>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        Self::login_with_mfa(client, username, password, |_| None).await
+    }
+
+    /// Like `login`, asking `mfa` for the code when Cognito requires a
+    /// second factor, `None` aborts the login
+    pub async fn login_with_mfa<U, P, F>(
+        client: &HoClient,
+        username: U,
+        password: P,
+        mut mfa: F,
+    ) -> Result<Self>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+        F: FnMut(&MfaChallenge) -> Option<String>,
+    {
+        let mut step = Self::start_login(client, username, password).await?;
 
+        loop {
+            let pending = match step {
+                LoginStep::Authenticated(auth) => return Ok(auth),
+                LoginStep::ChallengeRequired(pending) => pending,
+            };
+
+            let name = pending.challenge.name().as_str().to_string();
+            let Challenge::Mfa(challenge) = pending.challenge() else {
+                return Err(Error::ChallengeRequired(name));
+            };
+            let code = mfa(challenge).ok_or(Error::MfaCodeRequired(name))?;
+
+            step = pending
+                .respond(ChallengeAnswer::MfaCode(code.into()))
+                .await?;
+        }
+    }
+
+    /// Start the SRP flow through `client`, stopping at the first challenge
+    /// the caller has to answer
+    pub async fn start_login<U, P>(client: &HoClient, username: U, password: P) -> Result<LoginStep>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
+        Self::start_login_with_device(client, username, password, None).await
+    }
+
+    /// Like `start_login`, offering `device` from an earlier session so
+    /// Cognito can skip MFA
+    pub async fn start_login_with_device<U, P>(
+        client: &HoClient,
+        username: U,
+        password: P,
+        device: Option<HoDevice>,
+    ) -> Result<LoginStep>
+    where
+        U: AsRef<str>,
+        P: AsRef<str>,
+    {
         let username = username.as_ref();
         let password = password.as_ref();
+        let endpoints = client.endpoints();
 
         // Step 1: Create SRP client and generate authentication parameters
-        let user = User::new(USER_POOL_ID, username, password);
-        let srp_client = SrpClient::new(user, CLIENT_ID, None); // None = no client secret
+        let user = User::new(&endpoints.user_pool_id, username, password);
+        let srp_client = SrpClient::new(user, &endpoints.client_id, None); // None = no client secret
 
         let auth_params = srp_client.get_auth_parameters();
 
         // Step 2: Initiate auth with SRP_A
         let mut auth_parameters = HashMap::new();
-        auth_parameters.insert("USERNAME".to_string(), auth_params.username.clone());
-        auth_parameters.insert("SRP_A".to_string(), auth_params.a.clone());
+        auth_parameters.insert("USERNAME".to_string(), auth_params.username.as_str().into());
+        auth_parameters.insert("SRP_A".to_string(), auth_params.a.as_str().into());
+        if let Some(device) = &device {
+            auth_parameters.insert("DEVICE_KEY".to_string(), device.key.as_str().into());
+        }
 
         let initiate_request = InitiateAuthRequest {
             auth_flow: "USER_SRP_AUTH".to_string(),
-            client_id: CLIENT_ID.to_string(),
+            client_id: endpoints.client_id.clone(),
             auth_parameters,
             client_metadata: HashMap::new(),
         };
 
-        let initiate_response = client
-            .post(COGNITO_ENDPOINT)
-            .header("Content-Type", "application/x-amz-json-1.1")
-            .header(
-                "X-Amz-Target",
-                "AWSCognitoIdentityProviderService.InitiateAuth",
-            )
-            .json(&initiate_request)
-            .send()
-            .await?
-            .json::<InitiateAuthResponse>()
-            .await?;
-
-        // Step 3: Verify the challenge and generate password verifier
-        let verification = srp_client.verify(
-            &initiate_response.challenge_parameters.secret_block,
-            &initiate_response.challenge_parameters.user_id_for_srp,
-            &initiate_response.challenge_parameters.salt,
-            &initiate_response.challenge_parameters.srp_b,
-        )?;
-
-        // Step 4: Build challenge response
-        let mut challenge_responses = HashMap::new();
-        challenge_responses.insert(
-            "USERNAME".to_string(),
-            initiate_response.challenge_parameters.username.clone(),
-        );
-        challenge_responses.insert(
-            "PASSWORD_CLAIM_SECRET_BLOCK".to_string(),
-            verification.password_claim_secret_block,
-        );
-        challenge_responses.insert("TIMESTAMP".to_string(), verification.timestamp);
-        challenge_responses.insert(
-            "PASSWORD_CLAIM_SIGNATURE".to_string(),
-            verification.password_claim_signature,
-        );
-
-        let respond_request = RespondToAuthChallengeRequest {
-            challenge_name: "PASSWORD_VERIFIER".to_string(),
-            client_id: CLIENT_ID.to_string(),
-            challenge_responses,
-            client_metadata: HashMap::new(),
+        let response = cognito_request(client, "InitiateAuth", &initiate_request).await?;
+
+        // Step 3: Answer the challenges until the tokens are issued
+        let login = Login {
+            client,
+            srp_client: Some(&srp_client),
+            device,
+        };
+        login.advance(username.to_string(), response).await
+    }
+
+    // Exchange Cognito's tokens for the Hydro Ottawa JWT at the end of a login
+    async fn authenticated(
+        client: &HoClient,
+        auth_result: AuthenticationResult,
+        device: Option<HoDevice>,
+    ) -> Result<Self> {
+        let refresh_token = auth_result
+            .refresh_token
+            .ok_or_else(|| Error::MissingToken("RefreshToken".to_string()))?;
+
+        let jwt_token = app_token(client, &auth_result.id_token, &auth_result.access_token).await?;
+
+        // The tokens are good either way, a device that can't be confirmed
+        // only means MFA on the next login
+        let device = match auth_result.new_device_metadata {
+            Some(metadata) => {
+                match confirm_device(client, &auth_result.access_token, metadata).await {
+                    Ok(confirmed) => Some(confirmed),
+                    Err(e) => {
+                        warn!("Unable to remember the device: {e}");
+                        device
+                    }
+                }
+            }
+            None => device,
         };
 
-        // Step 5: Respond to challenge and get tokens
-        let auth_result = client
-            .post(COGNITO_ENDPOINT)
-            .header("Content-Type", "application/x-amz-json-1.1")
-            .header(
-                "X-Amz-Target",
-                "AWSCognitoIdentityProviderService.RespondToAuthChallenge",
-            )
-            .json(&respond_request)
-            .send()
-            .await?
-            .json::<RespondToAuthChallengeResponse>()
-            .await?;
-
-        // Step 6: Exchange Cognito tokens for Hydro Ottawa JWT
-        let app_token_url = format!("{HO_API_URI}/app-token");
-        let response = client
-            .get(&app_token_url)
-            .header("Accept", "application/json")
-            .header("x-id", &auth_result.authentication_result.id_token)
-            .header("x-access", &auth_result.authentication_result.access_token)
-            .send()
-            .await?;
-
-        // Extract the custom JWT from the response header
-        let jwt_token = response
-            .headers()
-            .get("x-amzn-remapped-authorization")
-            .ok_or_else(|| Error::MissingHeader("x-amzn-remapped-authorization".to_string()))?
-            .to_str()?
-            .strip_prefix("Bearer ")
-            .ok_or_else(|| {
-                Error::InvalidTokenFormat("Token doesn't start with 'Bearer '".to_string())
-            })?
-            .to_string();
-
-        Ok(Self {
+        let session = HoSession {
             jwt_token,
-            id_token: auth_result.authentication_result.id_token,
-            access_token: auth_result.authentication_result.access_token,
-        })
+            id_token: auth_result.id_token,
+            access_token: auth_result.access_token,
+            refresh_token,
+            expires_at: expires_at(auth_result.expires_in),
+            device,
+        };
+
+        Ok(Self::resume(client, session))
+    }
+
+    /// Resume from previously issued tokens, e.g. restored from disk
+    #[must_use]
+    pub fn from_session(session: HoSession) -> Self {
+        Self::resume(&HoClient::default(), session)
+    }
+
+    /// Like `from_session`, refreshing through `client`
+    #[must_use]
+    pub fn resume(client: &HoClient, session: HoSession) -> Self {
+        Self {
+            client: client.clone(),
+            session: RwLock::new(session),
+        }
+    }
+
+    /// Snapshot of the current tokens
+    pub async fn session(&self) -> HoSession {
+        self.session.read().await.clone()
+    }
+
+    /// Claims of the current tokens, e.g. the user and when they expire
+    pub async fn claims(&self) -> Result<SessionClaims> {
+        SessionClaims::decode(&*self.session.read().await)
+    }
+
+    /// Get new Cognito tokens with `REFRESH_TOKEN_AUTH` and redo the `/app-token` exchange
+    pub async fn refresh(&self) -> Result<()> {
+        let mut session = self.session.write().await;
+        self.refresh_locked(&mut session).await
+    }
+
+    /// Refresh only if the tokens are about to expire
+    pub async fn refresh_if_expiring(&self) -> Result<()> {
+        let mut session = self.session.write().await;
+
+        if session.is_expiring() {
+            self.refresh_locked(&mut session).await?;
+        }
+        Ok(())
+    }
+
+    /// Refresh after the API rejected `rejected_jwt`, unless a concurrent
+    /// caller already replaced it
+    pub(crate) async fn refresh_rejected(&self, rejected_jwt: &str) -> Result<()> {
+        let mut session = self.session.write().await;
+
+        if session.jwt_token.expose() == rejected_jwt {
+            self.refresh_locked(&mut session).await?;
+        }
+        Ok(())
+    }
+
+    async fn refresh_locked(&self, session: &mut HoSession) -> Result<()> {
+        let mut auth_parameters = HashMap::new();
+        auth_parameters.insert("REFRESH_TOKEN".to_string(), session.refresh_token.clone());
+        // Refresh tokens of a remembered device are bound to it
+        if let Some(device) = &session.device {
+            auth_parameters.insert("DEVICE_KEY".to_string(), device.key.as_str().into());
+        }
+
+        let refresh_request = InitiateAuthRequest {
+            auth_flow: "REFRESH_TOKEN_AUTH".to_string(),
+            client_id: self.client.endpoints.client_id.clone(),
+            auth_parameters,
+            client_metadata: HashMap::new(),
+        };
+
+        let auth_result: AuthResultResponse =
+            cognito_request(&self.client, "InitiateAuth", &refresh_request).await?;
+        let auth_result = auth_result.authentication_result;
+
+        let jwt_token = app_token(
+            &self.client,
+            &auth_result.id_token,
+            &auth_result.access_token,
+        )
+        .await?;
+
+        session.jwt_token = jwt_token;
+        session.id_token = auth_result.id_token;
+        session.access_token = auth_result.access_token;
+        if let Some(refresh_token) = auth_result.refresh_token {
+            session.refresh_token = refresh_token;
+        }
+        session.expires_at = expires_at(auth_result.expires_in);
+
+        Ok(())
     }
 }
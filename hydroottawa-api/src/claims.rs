@@ -0,0 +1,115 @@
+//! Claims of the session tokens, decoded without checking the signatures
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::{
+    auth::HoSession,
+    error::{Error, Result},
+    secret::Secret,
+};
+
+/// Registered claims of a JWT, the rest are kept in `extra`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Claims {
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub exp: Option<DateTime<Utc>>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub iat: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Claims {
+    /// Payload of `token`, the signature is ignored
+    pub fn decode(token: &Secret) -> Result<Self> {
+        let payload = token
+            .expose()
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| Error::InvalidTokenFormat("Token isn't a JWT".to_string()))?;
+
+        let payload = BASE64_URL
+            .decode(payload.trim_end_matches('='))
+            .map_err(|e| Error::InvalidTokenFormat(format!("Invalid JWT payload: {e}")))?;
+
+        Ok(serde_json::from_slice(&payload)?)
+    }
+
+    /// `username` of access tokens, `cognito:username` of ID tokens
+    #[must_use]
+    pub fn username(&self) -> Option<&str> {
+        ["username", "cognito:username"]
+            .iter()
+            .find_map(|name| self.extra.get(*name)?.as_str())
+    }
+
+    /// Claims naming an account, e.g. `accountId` or `custom:account_id`
+    #[must_use]
+    pub fn account_claims(&self) -> BTreeMap<&str, &Value> {
+        self.extra
+            .iter()
+            .filter(|(name, _)| name.to_ascii_lowercase().contains("account"))
+            .map(|(name, value)| (name.as_str(), value))
+            .collect()
+    }
+
+    /// The first account claim holding a string or a number
+    #[must_use]
+    pub fn account_id(&self) -> Option<String> {
+        self.account_claims()
+            .into_values()
+            .find_map(|value| match value {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+    }
+}
+
+/// Claims of the Cognito ID and access tokens and the Hydro Ottawa JWT
+#[derive(Debug, Clone, Default)]
+pub struct SessionClaims {
+    pub id: Claims,
+    pub access: Claims,
+    pub app: Claims,
+}
+
+impl SessionClaims {
+    /// Decodes the three tokens of `session`
+    pub fn decode(session: &HoSession) -> Result<Self> {
+        Ok(Self {
+            id: Claims::decode(&session.id_token)?,
+            access: Claims::decode(&session.access_token)?,
+            app: Claims::decode(&session.jwt_token)?,
+        })
+    }
+
+    /// Cognito username, from whichever token carries it
+    #[must_use]
+    pub fn username(&self) -> Option<&str> {
+        self.access.username().or_else(|| self.id.username())
+    }
+
+    /// Hydro Ottawa account of the session, from whichever token carries it
+    #[must_use]
+    pub fn account_id(&self) -> Option<String> {
+        [&self.app, &self.id, &self.access]
+            .into_iter()
+            .find_map(Claims::account_id)
+    }
+
+    /// Earliest expiry of the three tokens
+    #[must_use]
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        [&self.id, &self.access, &self.app]
+            .into_iter()
+            .filter_map(|claims| claims.exp)
+            .min()
+    }
+}
@@ -0,0 +1,164 @@
+use reqwest::{Client, Proxy};
+use std::{sync::Arc, time::Duration};
+
+use crate::error::Result;
+
+const HO_API_URI: &str = "https://api-myaccount.hydroottawa.com";
+const COGNITO_ENDPOINT: &str = "https://cognito-idp.ca-central-1.amazonaws.com/";
+const CLIENT_ID: &str = "7scfcis6ecucktmp4aqi1jk6cb";
+const USER_POOL_ID: &str = "ca-central-1_VYnwOhMBK";
+
+/// Hydro Ottawa and Cognito endpoints
+#[derive(Debug, Clone)]
+pub struct HoEndpoints {
+    pub api_url: String,
+    pub cognito_endpoint: String,
+    pub client_id: String,
+    pub user_pool_id: String,
+}
+
+impl Default for HoEndpoints {
+    fn default() -> Self {
+        Self {
+            api_url: HO_API_URI.to_string(),
+            cognito_endpoint: COGNITO_ENDPOINT.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            user_pool_id: USER_POOL_ID.to_string(),
+        }
+    }
+}
+
+/// HTTP client and endpoints shared by `HoAuth` and `HoApi`, cheap to clone
+#[derive(Debug, Clone)]
+pub struct HoClient {
+    pub(crate) http: Client,
+    pub(crate) endpoints: Arc<HoEndpoints>,
+}
+
+impl Default for HoClient {
+    fn default() -> Self {
+        Self {
+            http: Client::new(),
+            endpoints: Arc::new(HoEndpoints::default()),
+        }
+    }
+}
+
+impl HoClient {
+    #[must_use]
+    pub fn builder() -> HoClientBuilder {
+        HoClientBuilder::default()
+    }
+
+    #[must_use]
+    pub fn endpoints(&self) -> &HoEndpoints {
+        &self.endpoints
+    }
+
+    // API paths are relative to the configured base URL
+    pub(crate) fn api_url(&self, path: &str) -> String {
+        format!("{}{path}", self.endpoints.api_url.trim_end_matches('/'))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HoClientBuilder {
+    endpoints: HoEndpoints,
+    http: Option<Client>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl HoClientBuilder {
+    /// Hydro Ottawa API base URL
+    #[must_use]
+    pub fn api_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.endpoints.api_url = url.into();
+        self
+    }
+
+    /// Cognito identity provider endpoint
+    #[must_use]
+    pub fn cognito_endpoint<S: Into<String>>(mut self, url: S) -> Self {
+        self.endpoints.cognito_endpoint = url.into();
+        self
+    }
+
+    /// Cognito app client ID
+    #[must_use]
+    pub fn client_id<S: Into<String>>(mut self, client_id: S) -> Self {
+        self.endpoints.client_id = client_id.into();
+        self
+    }
+
+    /// Cognito user pool ID
+    #[must_use]
+    pub fn user_pool_id<S: Into<String>>(mut self, user_pool_id: S) -> Self {
+        self.endpoints.user_pool_id = user_pool_id.into();
+        self
+    }
+
+    /// Use an existing client, the timeout, proxy and user agent options are
+    /// then ignored
+    #[must_use]
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http = Some(client);
+        self
+    }
+
+    /// Total time allowed for each request
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Proxy all requests through `url`
+    #[must_use]
+    pub fn proxy<S: Into<String>>(mut self, url: S) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    #[must_use]
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn build(self) -> Result<HoClient> {
+        let http = if let Some(http) = self.http {
+            http
+        } else {
+            let mut builder = Client::builder();
+
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(proxy) = self.proxy {
+                builder = builder.proxy(Proxy::all(proxy)?);
+            }
+            if let Some(user_agent) = self.user_agent {
+                builder = builder.user_agent(user_agent);
+            }
+
+            builder.build()?
+        };
+
+        Ok(HoClient {
+            http,
+            endpoints: Arc::new(self.endpoints),
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, de::Error as _};
+
+/// Hydro Ottawa reports local times without an offset
+pub const HO_TZ: Tz = chrono_tz::America::Toronto;
+
+/// Timestamp in the Hydro Ottawa time zone
+pub type HoDateTime = DateTime<Tz>;
+
+const LOCAL_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Resolves a local time to the first matching instant after `after`
+///
+/// On the fall back day the repeated hour is ambiguous, the earlier offset is
+/// picked unless it isn't after `after`. Times skipped when springing forward
+/// keep the offset in effect before the gap.
+#[must_use]
+pub fn resolve_local(naive: &NaiveDateTime, after: Option<&HoDateTime>) -> Option<HoDateTime> {
+    let local = HO_TZ.from_local_datetime(naive);
+
+    if let Some(earliest) = local.earliest() {
+        return match (after, local.latest()) {
+            (Some(after), Some(latest)) if earliest <= *after => Some(latest),
+            _ => Some(earliest),
+        };
+    }
+
+    let hour = TimeDelta::hours(1);
+    HO_TZ
+        .from_local_datetime(&naive.checked_sub_signed(hour)?)
+        .earliest()?
+        .checked_add_signed(hour)
+}
+
+/// Hours in the local day `date`, 23 and 25 when daylight saving time
+/// starts and ends
+#[must_use]
+pub fn hours_in_day(date: &NaiveDate) -> Option<usize> {
+    let start = resolve_local(&date.and_time(NaiveTime::MIN), None)?;
+    let end = resolve_local(&date.succ_opt()?.and_time(NaiveTime::MIN), None)?;
+
+    usize::try_from(end.signed_duration_since(start).num_hours()).ok()
+}
+
+/// Accepts the API's offset-less local time as well as RFC 3339
+#[must_use]
+pub fn parse(s: &str) -> Option<HoDateTime> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&HO_TZ));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(s, LOCAL_FORMAT).ok()?;
+    resolve_local(&naive, None)
+}
+
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<HoDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    parse(&s).ok_or_else(|| D::Error::custom(format!("invalid date time: {s}")))
+}
+
+// Missing, null and empty all mean no date
+pub(crate) fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<HoDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => parse(&s)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid date time: {s}"))),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,180 @@
+//! Differences between the API responses and the fields the types know about
+
+use serde_json::Value;
+use std::{collections::BTreeSet, fmt};
+
+// Known fields of an object, nested objects and lists of objects list theirs
+enum Field {
+    Scalar(&'static str),
+    Object(&'static str, &'static [Field]),
+    List(&'static str, &'static [Field]),
+}
+
+use Field::{List, Object, Scalar};
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match self {
+            Scalar(name) | Object(name, _) | List(name, _) => name,
+        }
+    }
+}
+
+const ADDRESS: &[Field] = &[
+    Scalar("apartment"),
+    Scalar("city"),
+    Scalar("postalCode"),
+    Scalar("province"),
+    Scalar("streetName"),
+    Scalar("streetNumber"),
+];
+
+const ACCOUNT_INFORMATION: &[Field] = &[
+    Scalar("accountId"),
+    Scalar("businessPhoneNumber"),
+    Scalar("businessPhoneNumberExtension"),
+    Scalar("homePhoneNumber"),
+    Object("mailingAddress", ADDRESS),
+    Scalar("mobilePhoneNumber"),
+    Scalar("premiseId"),
+    Scalar("pseudoName"),
+    Object("serviceAddress", ADDRESS),
+];
+
+const USER_INFORMATION: &[Field] = &[
+    Scalar("languagePreference"),
+    Scalar("mfaEnabled"),
+    Scalar("mfaPhoneNumber"),
+    Scalar("socialSignIn"),
+    Scalar("username"),
+];
+
+const PROFILE: &[Field] = &[
+    Object("accountInformation", ACCOUNT_INFORMATION),
+    Object("userInformation", USER_INFORMATION),
+];
+
+const INTERVAL: &[Field] = &[
+    Scalar("startDateTime"),
+    Scalar("endDateTime"),
+    Scalar("rateBand"),
+    Scalar("hourlyUsage"),
+    Scalar("hourlyCost"),
+];
+
+const SUMMARY: &[Field] = &[
+    Scalar("accountId"),
+    Scalar("actualDate"),
+    Scalar("ratePlan"),
+    Scalar("billingPeriodStartDate"),
+    Scalar("billingPeriodEndDate"),
+    Scalar("totalUsage"),
+    Scalar("totalCost"),
+    Scalar("hourlyAverageUsage"),
+    Scalar("hourlyAverageCost"),
+    Scalar("totalOffPeakUsage"),
+    Scalar("totalOffPeakCost"),
+    Scalar("totalMidPeakUsage"),
+    Scalar("totalMidPeakCost"),
+    Scalar("totalOnPeakUsage"),
+    Scalar("totalOnPeakCost"),
+    Scalar("totalUloUsage"),
+    Scalar("totalUloCost"),
+    Scalar("numberOfHours"),
+];
+
+const HOURLY_USAGE: &[Field] = &[List("intervals", INTERVAL), Object("summary", SUMMARY)];
+
+/// Fields that appeared or disappeared compared with the known schema, as
+/// paths like `summary.totalUsage` or `intervals[].rateBand`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDrift {
+    /// Fields the types don't know about, kept in their `extra` maps
+    pub added: BTreeSet<String>,
+    /// Known fields absent from the response, read as their default
+    pub missing: BTreeSet<String>,
+}
+
+impl SchemaDrift {
+    /// Drift of a raw `/profile` response
+    #[must_use]
+    pub fn profile(value: &Value) -> Self {
+        let mut drift = Self::default();
+        drift.compare(value, PROFILE, "");
+        drift
+    }
+
+    /// Drift of a raw hourly usage response
+    #[must_use]
+    pub fn hourly_usage(value: &Value) -> Self {
+        let mut drift = Self::default();
+        drift.compare(value, HOURLY_USAGE, "");
+        drift
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.missing.is_empty()
+    }
+
+    /// Adds the fields of `other`
+    pub fn merge(&mut self, other: Self) {
+        self.added.extend(other.added);
+        self.missing.extend(other.missing);
+    }
+
+    // Anything but an object is left to deserialization to complain about
+    fn compare(&mut self, value: &Value, fields: &[Field], prefix: &str) {
+        let Value::Object(object) = value else {
+            return;
+        };
+
+        for key in object.keys() {
+            if !fields.iter().any(|field| field.name() == key) {
+                self.added.insert(format!("{prefix}{key}"));
+            }
+        }
+
+        for field in fields {
+            let Some(value) = object.get(field.name()) else {
+                self.missing.insert(format!("{prefix}{}", field.name()));
+                continue;
+            };
+
+            match field {
+                Scalar(_) => {}
+                Object(name, fields) => self.compare(value, fields, &format!("{prefix}{name}.")),
+                List(name, fields) => {
+                    let prefix = format!("{prefix}{name}[].");
+                    for item in value.as_array().into_iter().flatten() {
+                        self.compare(item, fields, &prefix);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |fields: &BTreeSet<String>| {
+            fields
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match (self.added.is_empty(), self.missing.is_empty()) {
+            (true, true) => write!(f, "no changes"),
+            (false, true) => write!(f, "new fields {}", join(&self.added)),
+            (true, false) => write!(f, "missing fields {}", join(&self.missing)),
+            (false, false) => write!(
+                f,
+                "new fields {}; missing fields {}",
+                join(&self.added),
+                join(&self.missing)
+            ),
+        }
+    }
+}
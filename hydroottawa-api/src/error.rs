@@ -1,4 +1,8 @@
 use aws_cognito_srp::SrpError;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{Response, StatusCode, header::HeaderMap, header::RETRY_AFTER};
+use serde::Deserialize;
+use std::{path::PathBuf, time::Duration};
 use thiserror::Error;
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -15,6 +19,8 @@ pub enum Error {
     Serialization(#[from] serde_json::Error),
     #[error(transparent)]
     InvalidHeaderValue(#[from] reqwest::header::ToStrError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 
     //
     // Custom
@@ -23,4 +29,143 @@ pub enum Error {
     MissingHeader(String),
     #[error("Invalid token format: {0}")]
     InvalidTokenFormat(String),
+    #[error("Missing token: {0}")]
+    MissingToken(String),
+    #[error("No code for the {0} challenge")]
+    MfaCodeRequired(String),
+    #[error("Unsupported auth challenge: {0}")]
+    UnsupportedChallenge(String),
+    #[error("Cognito answered with neither tokens nor a challenge")]
+    MissingChallenge,
+    #[error("Cognito asked for a remembered device, but the login has none")]
+    DeviceRequired,
+    #[error("Login requires answering the {0} challenge")]
+    ChallengeRequired(String),
+    #[error("Answer doesn't match the {0} challenge")]
+    InvalidChallengeAnswer(String),
+    #[error("Missing challenge parameter: {0}")]
+    MissingChallengeParameter(String),
+    #[error("No recorded response in {}", .0.display())]
+    FixtureNotFound(PathBuf),
+    #[error("No usage for {0}")]
+    UsageNotFound(NaiveDate),
+
+    //
+    // Cognito and Hydro Ottawa responses
+    //
+    #[error("Invalid credentials: {0}")]
+    InvalidCredentials(String),
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Too many requests{}", .retry_after.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    TooManyRequests { retry_after: Option<Duration> },
+    #[error("Account locked after too many failed password attempts")]
+    AccountLocked,
+    #[error("Session expired or revoked")]
+    TokenExpired,
+    #[error("Cognito {kind}: {message}")]
+    Cognito { kind: String, message: String },
+    #[error("API error {status}: {body}")]
+    Api { status: StatusCode, body: String },
+}
+
+// Cognito's error payload, `__type` may carry a namespace before a '#'
+#[derive(Debug, Deserialize)]
+struct CognitoError {
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(default, alias = "Message")]
+    message: String,
+}
+
+// Seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc)
+        .signed_duration_since(Utc::now())
+        .to_std()
+        .ok()
+}
+
+impl Error {
+    /// Failures worth retrying: connection problems, throttling and server
+    /// errors
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::HttpError(e) => e.is_connect() || e.is_timeout(),
+            Self::TooManyRequests { .. } => true,
+            Self::Api { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the server
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::TooManyRequests { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Error for an unsuccessful Cognito response
+    pub(crate) async fn from_cognito(response: Response) -> Self {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+
+        let Ok(error) = serde_json::from_str::<CognitoError>(&body) else {
+            return Self::Api { status, body };
+        };
+        let kind = error
+            .kind
+            .rsplit_once('#')
+            .map_or(error.kind.as_str(), |(_, kind)| kind);
+
+        match kind {
+            "TooManyRequestsException"
+            | "LimitExceededException"
+            | "TooManyFailedAttemptsException" => Self::TooManyRequests { retry_after },
+            // How Cognito reports an account locked after failed attempts,
+            // retrying only extends the lockout
+            "NotAuthorizedException" if error.message.contains("attempts exceeded") => {
+                Self::AccountLocked
+            }
+            "NotAuthorizedException" if error.message.contains("Token") => Self::TokenExpired,
+            "NotAuthorizedException" | "CodeMismatchException" | "ExpiredCodeException" => {
+                Self::InvalidCredentials(error.message)
+            }
+            "UserNotFoundException" => Self::UserNotFound,
+            _ => Self::Cognito {
+                kind: kind.to_string(),
+                message: error.message,
+            },
+        }
+    }
+
+    /// Error for an unsuccessful Hydro Ottawa response
+    pub(crate) async fn from_api(response: Response) -> Self {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::TokenExpired,
+            StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests { retry_after },
+            _ => match response.text().await {
+                Ok(body) => Self::Api { status, body },
+                Err(e) => e.into(),
+            },
+        }
+    }
 }
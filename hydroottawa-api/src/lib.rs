@@ -1,4 +1,12 @@
 pub mod api;
 pub mod auth;
+pub mod claims;
+pub mod client;
+pub mod datetime;
+pub mod drift;
 pub mod error;
+pub mod recording;
+pub mod retry;
+pub mod secret;
+pub mod source;
 pub mod types;
@@ -0,0 +1,196 @@
+use reqwest::{
+    Client, Request, RequestBuilder, Response, StatusCode,
+    header::{HeaderMap, HeaderName, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt::Write, fs, path::PathBuf};
+
+use crate::error::{Error, Result};
+
+// Never written to a recording
+const REDACTED_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "x-access",
+    "x-amzn-remapped-authorization",
+    "x-id",
+];
+const REDACTED: &str = "REDACTED";
+
+// Describe the body as sent, not as stored in the fixture
+const SKIPPED_HEADERS: [&str; 3] = ["content-encoding", "content-length", "transfer-encoding"];
+
+/// What `HoApi` does with the raw API exchanges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Recording {
+    /// Talk to the API
+    #[default]
+    Off,
+    /// Talk to the API and write every exchange to a fixture file in the
+    /// directory, auth headers redacted
+    Record(PathBuf),
+    /// Answer from the fixture files in the directory without any network
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    // JSON bodies are kept as JSON so fixtures stay readable and editable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+fn headers_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn request_body(request: &Request) -> Option<Value> {
+    let bytes = request.body()?.as_bytes()?;
+    serde_json::from_slice(bytes).ok()
+}
+
+// e.g. `post_usage_consumption_hourly_date-2025-12-31.json`
+fn fixture_name(request: &Request, body: Option<&Value>) -> String {
+    let mut name = format!(
+        "{}{}",
+        request.method().as_str().to_lowercase(),
+        request.url().path().replace('/', "_")
+    );
+
+    if let Some(Value::Object(fields)) = body {
+        for (key, value) in fields {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            let _ = write!(name, "_{key}-{value}");
+        }
+    }
+
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    format!("{name}.json")
+}
+
+fn into_response(recorded: RecordedResponse) -> Result<Response> {
+    let body = match (recorded.body, recorded.text) {
+        (Some(body), _) => serde_json::to_vec(&body)?,
+        (None, Some(text)) => text.into_bytes(),
+        (None, None) => Vec::new(),
+    };
+
+    let mut response = http::Response::new(body);
+    *response.status_mut() =
+        StatusCode::from_u16(recorded.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    for (name, value) in recorded.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    Ok(response.into())
+}
+
+impl Recording {
+    #[must_use]
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Self::Replay(_))
+    }
+
+    /// Sends `request` through `http`, recording or replaying it
+    pub(crate) async fn execute(&self, http: &Client, request: RequestBuilder) -> Result<Response> {
+        let dir = match self {
+            Self::Off => return Ok(request.send().await?),
+            Self::Record(dir) | Self::Replay(dir) => dir,
+        };
+
+        let request = request.build()?;
+        let body = request_body(&request);
+        let path = dir.join(fixture_name(&request, body.as_ref()));
+
+        if self.is_replay() {
+            let exchange: Exchange = match fs::read(&path) {
+                Ok(bytes) => serde_json::from_slice(&bytes)?,
+                Err(_) => return Err(Error::FixtureNotFound(path)),
+            };
+            return into_response(exchange.response);
+        }
+
+        let recorded_request = RecordedRequest {
+            method: request.method().to_string(),
+            path: request.url().path().to_string(),
+            headers: headers_map(request.headers()),
+            body,
+        };
+
+        let response = http.execute(request).await?;
+        let status = response.status();
+        let headers = headers_map(response.headers());
+        let text = response.text().await?;
+
+        let (body, text) = match serde_json::from_str(&text) {
+            Ok(body) => (Some(body), None),
+            Err(_) if text.is_empty() => (None, None),
+            Err(_) => (None, Some(text)),
+        };
+
+        let exchange = Exchange {
+            request: recorded_request,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers,
+                body,
+                text,
+            },
+        };
+
+        fs::create_dir_all(dir)?;
+        fs::write(&path, serde_json::to_vec_pretty(&exchange)?)?;
+
+        into_response(exchange.response)
+    }
+}
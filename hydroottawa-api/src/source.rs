@@ -0,0 +1,143 @@
+//! Where profiles and hourly usage come from, so consumers don't need to log in
+
+use chrono::NaiveDate;
+use std::{collections::BTreeMap, future::Future, path::PathBuf};
+
+use crate::{
+    api::HoApi,
+    auth::{HoAuth, HoSession},
+    client::HoClient,
+    drift::SchemaDrift,
+    error::{Error, Result},
+    recording::Recording,
+    types::{HoHourlyUsage, HoProfile},
+};
+
+/// Account profile and hourly usage by day
+pub trait UsageSource: Sync {
+    fn profile(&self) -> impl Future<Output = Result<HoProfile>> + Send;
+
+    fn hourly(&self, date: &NaiveDate) -> impl Future<Output = Result<HoHourlyUsage>> + Send;
+
+    /// Fields that appeared or disappeared in the responses so far, empty
+    /// for sources that don't track them
+    fn drift(&self) -> SchemaDrift {
+        SchemaDrift::default()
+    }
+
+    /// Hourly usage for each of `days` in order, each day with its own result
+    fn hourly_days(
+        &self,
+        days: Vec<NaiveDate>,
+    ) -> impl Future<Output = Vec<(NaiveDate, Result<HoHourlyUsage>)>> + Send {
+        async move {
+            let mut usages = Vec::with_capacity(days.len());
+            for day in days {
+                usages.push((day, self.hourly(&day).await));
+            }
+            usages
+        }
+    }
+}
+
+/// The Hydro Ottawa API with a logged in session
+pub struct LiveSource<'a> {
+    api: &'a HoApi,
+    auth: &'a HoAuth,
+}
+
+impl<'a> LiveSource<'a> {
+    #[must_use]
+    pub fn new(api: &'a HoApi, auth: &'a HoAuth) -> Self {
+        Self { api, auth }
+    }
+}
+
+impl UsageSource for LiveSource<'_> {
+    async fn profile(&self) -> Result<HoProfile> {
+        self.api.profile(self.auth).await
+    }
+
+    async fn hourly(&self, date: &NaiveDate) -> Result<HoHourlyUsage> {
+        self.api.hourly(self.auth, date).await
+    }
+
+    fn drift(&self) -> SchemaDrift {
+        self.api.drift()
+    }
+
+    // Concurrent, see `HoApi::hourly_days`
+    async fn hourly_days(&self, days: Vec<NaiveDate>) -> Vec<(NaiveDate, Result<HoHourlyUsage>)> {
+        self.api.hourly_days(self.auth, days).await
+    }
+}
+
+/// A directory written by `Recording::Record` (`--record`), replayed
+/// without any network
+pub struct FileSource {
+    api: HoApi,
+    auth: HoAuth,
+}
+
+impl FileSource {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let client = HoClient::default();
+
+        Self {
+            api: HoApi::with_client(client.clone()).with_recording(Recording::Replay(dir.into())),
+            auth: HoAuth::resume(&client, HoSession::replayed()),
+        }
+    }
+}
+
+impl UsageSource for FileSource {
+    async fn profile(&self) -> Result<HoProfile> {
+        self.api.profile(&self.auth).await
+    }
+
+    async fn hourly(&self, date: &NaiveDate) -> Result<HoHourlyUsage> {
+        self.api.hourly(&self.auth, date).await
+    }
+
+    fn drift(&self) -> SchemaDrift {
+        self.api.drift()
+    }
+}
+
+/// Fixed profile and usage held in memory, for tests
+#[derive(Debug, Clone)]
+pub struct MemorySource {
+    profile: HoProfile,
+    usages: BTreeMap<NaiveDate, HoHourlyUsage>,
+}
+
+impl MemorySource {
+    #[must_use]
+    pub fn new(profile: HoProfile) -> Self {
+        Self {
+            profile,
+            usages: BTreeMap::new(),
+        }
+    }
+
+    /// Serve `usage` for its `summary.actual_date`
+    #[must_use]
+    pub fn with_hourly(mut self, usage: HoHourlyUsage) -> Self {
+        self.usages.insert(usage.summary.actual_date, usage);
+        self
+    }
+}
+
+impl UsageSource for MemorySource {
+    async fn profile(&self) -> Result<HoProfile> {
+        Ok(self.profile.clone())
+    }
+
+    async fn hourly(&self, date: &NaiveDate) -> Result<HoHourlyUsage> {
+        self.usages
+            .get(date)
+            .cloned()
+            .ok_or(Error::UsageNotFound(*date))
+    }
+}
@@ -1,83 +1,303 @@
-use serde::{Deserialize, Serialize};
+use chrono::{NaiveDate, Offset, TimeDelta, TimeZone};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
 
-#[derive(Debug, Deserialize)]
+use crate::datetime::{self, HO_TZ, HoDateTime, resolve_local};
+
+// Null and missing fields both fall back to the default, the portal leaves
+// out or nulls whatever an account doesn't have. Fields the types don't know
+// end up in `extra`, see `drift` for reporting them.
+fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoProfile {
     pub account_information: HoAccountInformation,
     pub user_information: HoUserInformation,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoAccountInformation {
+    #[serde(default, deserialize_with = "nullable")]
     pub account_id: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub business_phone_number: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub business_phone_number_extension: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub home_phone_number: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub mailing_address: HoAddress,
+    #[serde(default, deserialize_with = "nullable")]
     pub mobile_phone_number: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub premise_id: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub pseudo_name: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub service_address: HoAddress,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoAddress {
+    #[serde(default, deserialize_with = "nullable")]
     pub apartment: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub city: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub postal_code: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub province: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub street_name: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub street_number: String,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoUserInformation {
+    #[serde(default, deserialize_with = "nullable")]
     pub language_preference: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub mfa_enabled: bool,
+    #[serde(default, deserialize_with = "nullable")]
     pub mfa_phone_number: String,
+    #[serde(default, deserialize_with = "nullable")]
     pub social_sign_in: bool,
+    #[serde(default, deserialize_with = "nullable")]
     pub username: String,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+// Upper case alphanumerics only so "Off-Peak", "OFF_PEAK" and "offPeak" all match
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Price band of an hourly interval. Spellings of a known band are all read
+/// as the same variant and written back in one canonical form, e.g.
+/// `Off-Peak` as `OffPeak`, while unknown bands keep the portal's string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum RateBand {
+    Tier1,
+    Tier2,
+    OffPeak,
+    MidPeak,
+    OnPeak,
+    UltraLowOvernight,
+    Unknown(String),
+}
+
+impl From<String> for RateBand {
+    fn from(s: String) -> Self {
+        match normalize(&s).as_str() {
+            "TIER1" => Self::Tier1,
+            "TIER2" => Self::Tier2,
+            "OFFPEAK" => Self::OffPeak,
+            "MIDPEAK" => Self::MidPeak,
+            "ONPEAK" => Self::OnPeak,
+            "ULO" | "ULTRALOWOVERNIGHT" => Self::UltraLowOvernight,
+            _ => Self::Unknown(s),
+        }
+    }
+}
+
+impl Default for RateBand {
+    fn default() -> Self {
+        Self::Unknown(String::new())
+    }
+}
+
+impl From<RateBand> for String {
+    fn from(band: RateBand) -> Self {
+        band.to_string()
+    }
+}
+
+impl fmt::Display for RateBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tier1 => write!(f, "Tier1"),
+            Self::Tier2 => write!(f, "Tier2"),
+            Self::OffPeak => write!(f, "OffPeak"),
+            Self::MidPeak => write!(f, "MidPeak"),
+            Self::OnPeak => write!(f, "OnPeak"),
+            Self::UltraLowOvernight => write!(f, "ULO"),
+            Self::Unknown(s) => write!(f, "{s}"),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Pricing plan of the account, normalised the same way as `RateBand`, e.g.
+/// `TimeOfUse` is written back as `TOU`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum RatePlan {
+    Tiered,
+    TimeOfUse,
+    UltraLowOvernight,
+    Unknown(String),
+}
+
+impl From<String> for RatePlan {
+    fn from(s: String) -> Self {
+        match normalize(&s).as_str() {
+            "TIERED" => Self::Tiered,
+            "TOU" | "TIMEOFUSE" => Self::TimeOfUse,
+            "ULO" | "ULTRALOWOVERNIGHT" => Self::UltraLowOvernight,
+            _ => Self::Unknown(s),
+        }
+    }
+}
+
+impl Default for RatePlan {
+    fn default() -> Self {
+        Self::Unknown(String::new())
+    }
+}
+
+impl From<RatePlan> for String {
+    fn from(plan: RatePlan) -> Self {
+        plan.to_string()
+    }
+}
+
+impl fmt::Display for RatePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tiered => write!(f, "TIERED"),
+            Self::TimeOfUse => write!(f, "TOU"),
+            Self::UltraLowOvernight => write!(f, "ULO"),
+            Self::Unknown(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoInterval {
-    pub start_date_time: String,
-    pub end_date_time: String,
-    pub rate_band: String,
+    #[serde(deserialize_with = "datetime::deserialize")]
+    pub start_date_time: HoDateTime,
+    #[serde(deserialize_with = "datetime::deserialize")]
+    pub end_date_time: HoDateTime,
+    #[serde(default, deserialize_with = "nullable")]
+    pub rate_band: RateBand,
+    #[serde(default, deserialize_with = "nullable")]
     pub hourly_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub hourly_cost: f64,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoSummary {
+    #[serde(default, deserialize_with = "nullable")]
     pub account_id: String,
-    pub actual_date: String,
-    pub rate_plan: String,
-    pub billing_period_start_date: String,
-    pub billing_period_end_date: String,
+    pub actual_date: NaiveDate,
+    #[serde(default, deserialize_with = "nullable")]
+    pub rate_plan: RatePlan,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
+    pub billing_period_start_date: Option<HoDateTime>,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
+    pub billing_period_end_date: Option<HoDateTime>,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub hourly_average_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub hourly_average_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_off_peak_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_off_peak_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_mid_peak_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_mid_peak_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_on_peak_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_on_peak_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_ulo_usage: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub total_ulo_cost: f64,
+    #[serde(default, deserialize_with = "nullable")]
     pub number_of_hours: u32,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HoHourlyUsage {
+    #[serde(default, deserialize_with = "deserialize_intervals")]
     pub intervals: Vec<HoInterval>,
     pub summary: HoSummary,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+// The repeated hour of a fall back day shows up twice with the same local
+// time, only the order of the intervals tells which offset applies
+fn deserialize_intervals<'de, D>(deserializer: D) -> Result<Vec<HoInterval>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut intervals = nullable::<D, Vec<HoInterval>>(deserializer)?;
+    let mut previous: Option<HoDateTime> = None;
+
+    for interval in &mut intervals {
+        if let Some(start) =
+            resolve_local(&interval.start_date_time.naive_local(), previous.as_ref())
+            && previous.is_some_and(|p| interval.start_date_time <= p)
+        {
+            interval.start_date_time = start;
+        }
+        // A wall clock end that's ambiguous, skipped or on the other side of
+        // the change from the start can't be trusted, but every interval is
+        // an hour long. Any other day keeps the end the API reported.
+        let end = interval.end_date_time;
+        let crosses_change = HO_TZ
+            .from_local_datetime(&end.naive_local())
+            .single()
+            .is_none()
+            || end.offset().fix() != interval.start_date_time.offset().fix()
+            || end <= interval.start_date_time;
+        if crosses_change
+            && let Some(end) = interval
+                .start_date_time
+                .checked_add_signed(TimeDelta::hours(1))
+        {
+            interval.end_date_time = end;
+        }
+        previous = Some(interval.start_date_time);
+    }
+
+    Ok(intervals)
 }
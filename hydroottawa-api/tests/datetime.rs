@@ -0,0 +1,77 @@
+use chrono::{NaiveDate, TimeDelta};
+use hydroottawa_api::{datetime::hours_in_day, types::HoHourlyUsage};
+use hydroottawa_mock::hourly_fixture;
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+// Usage for `date` as the portal serves it has `hours` contiguous intervals
+fn assert_contiguous(date: NaiveDate, hours: usize) -> TestResult {
+    let usage: HoHourlyUsage = serde_json::from_value(hourly_fixture(date))?;
+    assert_eq!(usage.intervals.len(), hours);
+    assert_eq!(hours_in_day(&date), Some(hours));
+
+    for interval in &usage.intervals {
+        assert_eq!(
+            interval
+                .end_date_time
+                .signed_duration_since(interval.start_date_time),
+            TimeDelta::hours(1),
+            "{interval:?}"
+        );
+    }
+    for pair in usage.intervals.windows(2) {
+        if let [first, second] = pair {
+            assert_eq!(first.end_date_time, second.start_date_time, "{pair:?}");
+        }
+    }
+
+    let first = usage.intervals.first().ok_or("no intervals")?;
+    let last = usage.intervals.last().ok_or("no intervals")?;
+    assert_eq!(first.start_date_time.date_naive(), date);
+    assert_eq!(
+        last.end_date_time.date_naive(),
+        date.succ_opt().ok_or("invalid date")?
+    );
+    Ok(())
+}
+
+#[test]
+fn resolves_regular_day() -> TestResult {
+    assert_contiguous(
+        NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?,
+        24,
+    )
+}
+
+#[test]
+fn keeps_reported_end_on_regular_day() -> TestResult {
+    let date = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+    let mut fixture = hourly_fixture(date);
+    fixture["intervals"][0]["endDateTime"] = "2025-12-31T00:30:00".into();
+
+    let usage: HoHourlyUsage = serde_json::from_value(fixture)?;
+    let first = usage.intervals.first().ok_or("no intervals")?;
+    assert_eq!(
+        first
+            .end_date_time
+            .signed_duration_since(first.start_date_time),
+        TimeDelta::minutes(30)
+    );
+    Ok(())
+}
+
+#[test]
+fn resolves_spring_forward_day() -> TestResult {
+    assert_contiguous(
+        NaiveDate::from_ymd_opt(2026, 3, 8).ok_or("invalid date")?,
+        23,
+    )
+}
+
+#[test]
+fn resolves_fall_back_day() -> TestResult {
+    assert_contiguous(
+        NaiveDate::from_ymd_opt(2025, 11, 2).ok_or("invalid date")?,
+        25,
+    )
+}
@@ -0,0 +1,562 @@
+use chrono::{NaiveDate, TimeDelta, Utc};
+use hydroottawa_api::{
+    api::HoApi,
+    auth::{Challenge, ChallengeAnswer, HoAuth, LoginStep, MfaKind},
+    client::HoClient,
+    error::Error,
+    recording::Recording,
+    retry::RetryPolicy,
+    types::RatePlan,
+};
+use hydroottawa_mock::{ACCOUNT_ID, MfaMethod, MockServer, PASSWORD, USERNAME};
+use reqwest::StatusCode;
+use std::time::{Duration, Instant};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+fn client(server: &MockServer) -> hydroottawa_api::error::Result<HoClient> {
+    HoClient::builder()
+        .api_url(server.api_url())
+        .cognito_endpoint(server.cognito_endpoint())
+        .build()
+}
+
+#[tokio::test]
+async fn login_and_fetch() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client);
+
+    let profile = api.profile(&auth).await?;
+    assert_eq!(profile.account_information.account_id, ACCOUNT_ID);
+    assert_eq!(profile.user_information.username, USERNAME);
+
+    let date = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+    let usage = api.hourly(&auth, &date).await?;
+    assert_eq!(usage.intervals.len(), 24);
+    assert_eq!(usage.summary.actual_date, date);
+    assert_eq!(usage.summary.rate_plan, RatePlan::Tiered);
+
+    let stats = server.stats();
+    assert_eq!(stats.logins, 1);
+    assert_eq!(stats.refreshes, 0);
+    assert_eq!(stats.api_requests, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_tokens_out_of_debug_output() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let session = auth.session().await;
+
+    let debug = format!("{session:?}");
+    for token in [
+        &session.jwt_token,
+        &session.id_token,
+        &session.access_token,
+        &session.refresh_token,
+    ] {
+        assert!(!token.is_empty());
+        assert!(!debug.contains(token.expose()));
+        assert_eq!(token.to_string(), "[REDACTED]");
+    }
+
+    let answer = ChallengeAnswer::MfaCode("123456".into());
+    assert!(!format!("{answer:?}").contains("123456"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn decodes_token_claims() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+    server.set_token_lifetime(600);
+
+    let before = Utc::now();
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let claims = auth.claims().await?;
+
+    assert_eq!(claims.username(), Some(USERNAME));
+    assert_eq!(claims.id.sub.as_deref(), Some(USERNAME));
+    assert_eq!(claims.account_id().as_deref(), Some(ACCOUNT_ID));
+    assert!(claims.app.account_claims().contains_key("accountId"));
+
+    let expires_at = claims.expires_at().ok_or("no exp claim")?;
+    let iat = claims.access.iat.ok_or("no iat claim")?;
+    assert_eq!(
+        expires_at.signed_duration_since(iat),
+        TimeDelta::seconds(600)
+    );
+    assert!(iat.timestamp() >= before.timestamp());
+    Ok(())
+}
+
+#[tokio::test]
+async fn wrong_password_is_rejected() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let result = HoAuth::login(&client, USERNAME, "hunter2").await;
+    assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+
+    let result = HoAuth::login(&client, "nobody@example.com", PASSWORD).await;
+    assert!(matches!(result, Err(Error::UserNotFound)));
+    assert_eq!(server.stats().logins, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_locked_account() -> TestResult {
+    let server = MockServer::start().await?;
+    server.lock_account(USERNAME);
+    let client = client(&server)?;
+
+    let result = HoAuth::login(&client, USERNAME, PASSWORD).await;
+    let Err(e) = result else {
+        return Err("locked account logged in".into());
+    };
+    assert!(matches!(e, Error::AccountLocked));
+    assert!(!e.is_transient());
+    assert_eq!(e.retry_after(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn answers_sms_mfa() -> TestResult {
+    let server = MockServer::start().await?;
+    server.enable_mfa(USERNAME, MfaMethod::Sms, "123456");
+    let client = client(&server)?;
+
+    let mut asked = Vec::new();
+    let auth = HoAuth::login_with_mfa(&client, USERNAME, PASSWORD, |challenge| {
+        asked.push(challenge.clone());
+        Some("123456".to_string())
+    })
+    .await?;
+
+    assert_eq!(asked.len(), 1);
+    assert_eq!(asked[0].kind, MfaKind::Sms);
+    assert!(asked[0].destination.is_some());
+
+    HoApi::with_client(client).profile(&auth).await?;
+    assert_eq!(server.stats().logins, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_wrong_mfa_code() -> TestResult {
+    let server = MockServer::start().await?;
+    server.enable_mfa(USERNAME, MfaMethod::SoftwareToken, "123456");
+    let client = client(&server)?;
+
+    let result = HoAuth::login_with_mfa(&client, USERNAME, PASSWORD, |challenge| {
+        assert_eq!(challenge.kind, MfaKind::SoftwareToken);
+        Some("654321".to_string())
+    })
+    .await;
+    assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+
+    let result = HoAuth::login(&client, USERNAME, PASSWORD).await;
+    assert!(matches!(result, Err(Error::MfaCodeRequired(_))));
+    assert_eq!(server.stats().logins, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn sets_new_password() -> TestResult {
+    let server = MockServer::start().await?;
+    server.require_new_password(USERNAME, &["name"]);
+    let client = client(&server)?;
+
+    let result = HoAuth::login(&client, USERNAME, PASSWORD).await;
+    assert!(matches!(result, Err(Error::ChallengeRequired(_))));
+
+    let LoginStep::ChallengeRequired(pending) =
+        HoAuth::start_login(&client, USERNAME, PASSWORD).await?
+    else {
+        return Err("expected a challenge".into());
+    };
+    let Challenge::NewPasswordRequired {
+        required_attributes,
+    } = pending.challenge()
+    else {
+        return Err("expected NEW_PASSWORD_REQUIRED".into());
+    };
+    assert_eq!(required_attributes, &["name"]);
+
+    let answer = ChallengeAnswer::NewPassword {
+        password: "a new password".into(),
+        attributes: [("name".to_string(), "Jane".to_string())].into(),
+    };
+    let LoginStep::Authenticated(auth) = pending.respond(answer).await? else {
+        return Err("expected to be authenticated".into());
+    };
+    HoApi::with_client(client.clone()).profile(&auth).await?;
+
+    assert!(HoAuth::login(&client, USERNAME, PASSWORD).await.is_err());
+    HoAuth::login(&client, USERNAME, "a new password").await?;
+    assert_eq!(server.stats().logins, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_mismatched_answer() -> TestResult {
+    let server = MockServer::start().await?;
+    server.require_new_password(USERNAME, &[]);
+    let client = client(&server)?;
+
+    let LoginStep::ChallengeRequired(pending) =
+        HoAuth::start_login(&client, USERNAME, PASSWORD).await?
+    else {
+        return Err("expected a challenge".into());
+    };
+
+    let result = pending
+        .respond(ChallengeAnswer::MfaCode("123456".into()))
+        .await;
+    assert!(matches!(result, Err(Error::InvalidChallengeAnswer(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_unsupported_challenge() -> TestResult {
+    let server = MockServer::start().await?;
+    server.force_challenge(USERNAME, "CUSTOM_CHALLENGE");
+    let client = client(&server)?;
+
+    let result = HoAuth::start_login(&client, USERNAME, PASSWORD).await;
+    assert!(matches!(result, Err(Error::UnsupportedChallenge(name)) if name == "CUSTOM_CHALLENGE"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_missing_challenge() -> TestResult {
+    let server = MockServer::start().await?;
+    server.force_challenge(USERNAME, "");
+    let client = client(&server)?;
+
+    let result = HoAuth::start_login(&client, USERNAME, PASSWORD).await;
+    assert!(matches!(result, Err(Error::MissingChallenge)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn remembers_device() -> TestResult {
+    let server = MockServer::start().await?;
+    server.enable_mfa(USERNAME, MfaMethod::SoftwareToken, "123456");
+    server.remember_devices();
+    let client = client(&server)?;
+
+    let auth =
+        HoAuth::login_with_mfa(&client, USERNAME, PASSWORD, |_| Some("123456".to_string())).await?;
+    let device = auth.session().await.device;
+    assert!(device.is_some());
+
+    // The device answers instead of the MFA code
+    let step = HoAuth::start_login_with_device(&client, USERNAME, PASSWORD, device).await?;
+    let LoginStep::Authenticated(auth) = step else {
+        return Err("remembered device still asked for MFA".into());
+    };
+    HoApi::with_client(client).profile(&auth).await?;
+    auth.refresh().await?;
+
+    assert_eq!(server.stats().logins, 2);
+    assert_eq!(server.stats().device_logins, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn logs_in_when_device_confirmation_fails() -> TestResult {
+    let server = MockServer::start().await?;
+    server.enable_mfa(USERNAME, MfaMethod::SoftwareToken, "123456");
+    server.remember_devices();
+    server.reject_devices();
+    let client = client(&server)?;
+
+    // The tokens are issued, only the device is lost
+    let auth =
+        HoAuth::login_with_mfa(&client, USERNAME, PASSWORD, |_| Some("123456".to_string())).await?;
+    assert!(auth.session().await.device.is_none());
+    HoApi::with_client(client).profile(&auth).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn requires_device_for_device_challenge() -> TestResult {
+    let server = MockServer::start().await?;
+    server.force_challenge(USERNAME, "DEVICE_SRP_AUTH");
+    let client = client(&server)?;
+
+    let result = HoAuth::start_login(&client, USERNAME, PASSWORD).await;
+    assert!(matches!(result, Err(Error::DeviceRequired)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn refreshes_rejected_tokens() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client);
+    let before = auth.session().await;
+
+    server.revoke_tokens();
+    api.profile(&auth).await?;
+
+    let after = auth.session().await;
+    assert_ne!(before.jwt_token, after.jwt_token);
+    assert_eq!(before.refresh_token, after.refresh_token);
+
+    let stats = server.stats();
+    assert_eq!(stats.refreshes, 1);
+    assert_eq!(stats.rejected, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refreshes_expiring_tokens() -> TestResult {
+    let server = MockServer::start().await?;
+    server.set_token_lifetime(60);
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    assert!(auth.session().await.is_expiring());
+
+    let api = HoApi::with_client(client);
+    api.profile(&auth).await?;
+
+    let stats = server.stats();
+    assert_eq!(stats.refreshes, 1);
+    assert_eq!(stats.rejected, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_expired_session() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let mut session = HoAuth::login(&client, USERNAME, PASSWORD)
+        .await?
+        .session()
+        .await;
+    session.refresh_token = "revoked".into();
+    let auth = HoAuth::resume(&client, session);
+
+    server.revoke_tokens();
+    let result = HoApi::with_client(client).profile(&auth).await;
+    assert!(matches!(result, Err(Error::TokenExpired)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_api_failures() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_retry_policy(RetryPolicy::none());
+
+    server.fail_api(429, 1);
+    let result = api.profile(&auth).await;
+    assert!(matches!(
+        result,
+        Err(Error::TooManyRequests { retry_after: Some(retry_after) })
+            if retry_after == Duration::from_secs(1)
+    ));
+
+    server.fail_api(503, 1);
+    let result = api.profile(&auth).await;
+    assert!(matches!(
+        result,
+        Err(Error::Api { status, .. }) if status == StatusCode::SERVICE_UNAVAILABLE
+    ));
+
+    api.profile(&auth).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn tolerates_schema_drift() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client);
+
+    api.profile(&auth).await?;
+    assert!(api.drift().is_empty());
+
+    server.patch_summary("totalCost", Some(serde_json::Value::Null));
+    server.patch_summary("billingPeriodEndDate", None);
+    server.patch_summary("totalEvUsage", Some(serde_json::json!(1.5)));
+
+    let date = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+    let usage = api.hourly(&auth, &date).await?;
+    assert!(usage.summary.total_cost.abs() < f64::EPSILON);
+    assert!(usage.summary.billing_period_end_date.is_none());
+    assert_eq!(
+        usage.summary.extra.get("totalEvUsage"),
+        Some(&serde_json::json!(1.5))
+    );
+
+    let drift = api.drift();
+    assert_eq!(
+        drift.added.into_iter().collect::<Vec<_>>(),
+        ["summary.totalEvUsage"]
+    );
+    assert_eq!(
+        drift.missing.into_iter().collect::<Vec<_>>(),
+        ["summary.billingPeriodEndDate"]
+    );
+    Ok(())
+}
+
+fn fast_retries(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_secs(5),
+    }
+}
+
+#[tokio::test]
+async fn retries_transient_failures() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_retry_policy(fast_retries(3));
+
+    server.fail_api(502, 2);
+    api.profile(&auth).await?;
+    assert_eq!(server.stats().api_requests, 3);
+
+    // Client errors aren't retried
+    server.fail_api(404, 1);
+    assert!(api.profile(&auth).await.is_err());
+    assert_eq!(server.stats().api_requests, 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn gives_up_after_retry_budget() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_retry_policy(fast_retries(2));
+
+    server.fail_api(500, 5);
+    let result = api.profile(&auth).await;
+    assert!(matches!(result, Err(Error::Api { .. })));
+    assert_eq!(server.stats().api_requests, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn waits_for_retry_after() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_retry_policy(fast_retries(1));
+
+    server.fail_api(429, 1);
+    let start = Instant::now();
+    api.profile(&auth).await?;
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_and_replays() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let date = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let session = auth.session().await;
+
+    let api =
+        HoApi::with_client(client.clone()).with_recording(Recording::Record(dir.path().into()));
+    let recorded = api.hourly(&auth, &date).await?;
+    api.profile(&auth).await?;
+    drop(server);
+
+    let mut files = 0;
+    for entry in std::fs::read_dir(dir.path())? {
+        let contents = std::fs::read_to_string(entry?.path())?;
+        assert!(!contents.contains(session.jwt_token.expose()));
+        assert!(!contents.contains(session.access_token.expose()));
+        assert!(!contents.contains(session.id_token.expose()));
+
+        // The gateway echoes the bearer JWT back in the response
+        let exchange: serde_json::Value = serde_json::from_str(&contents)?;
+        for (message, header) in [
+            ("request", "authorization"),
+            ("request", "x-access"),
+            ("request", "x-id"),
+            ("response", "x-amzn-remapped-authorization"),
+        ] {
+            assert_eq!(exchange[message]["headers"][header], "REDACTED");
+        }
+        files += 1;
+    }
+    assert_eq!(files, 2);
+
+    let api = HoApi::with_client(client).with_recording(Recording::Replay(dir.path().into()));
+    let replayed = api.hourly(&auth, &date).await?;
+    assert_eq!(
+        serde_json::to_value(&recorded)?,
+        serde_json::to_value(&replayed)?
+    );
+
+    let other_day = date.succ_opt().ok_or("invalid date")?;
+    let result = api.hourly(&auth, &other_day).await;
+    assert!(matches!(result, Err(Error::FixtureNotFound(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn resumes_saved_session() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let session = HoAuth::login(&client, USERNAME, PASSWORD)
+        .await?
+        .session()
+        .await;
+    let auth = HoAuth::resume(&client, session);
+
+    HoApi::with_client(client).profile(&auth).await?;
+    assert_eq!(server.stats().logins, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn hourly_range_in_date_order() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = client(&server)?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_max_concurrency(3);
+
+    let start = NaiveDate::from_ymd_opt(2025, 12, 25).ok_or("invalid date")?;
+    let end = NaiveDate::from_ymd_opt(2026, 1, 2).ok_or("invalid date")?;
+    let days = api.hourly_range(&auth, &start, &end).await;
+
+    assert_eq!(days.len(), 9);
+    for ((day, usage), expected) in days.into_iter().zip(start.iter_days()) {
+        assert_eq!(day, expected);
+        assert_eq!(usage?.summary.actual_date, expected);
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use hydroottawa_api::types::{RateBand, RatePlan};
+use serde_json::json;
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+#[test]
+fn reads_known_rate_bands() {
+    for (raw, band) in [
+        ("Tier1", RateBand::Tier1),
+        ("Tier2", RateBand::Tier2),
+        ("OffPeak", RateBand::OffPeak),
+        ("MidPeak", RateBand::MidPeak),
+        ("OnPeak", RateBand::OnPeak),
+        ("ULO", RateBand::UltraLowOvernight),
+    ] {
+        assert_eq!(RateBand::from(raw.to_string()), band, "{raw}");
+    }
+}
+
+#[test]
+fn reads_alternate_spellings() {
+    for raw in ["Off-Peak", "OFF_PEAK", "offPeak", "off peak"] {
+        assert_eq!(RateBand::from(raw.to_string()), RateBand::OffPeak, "{raw}");
+    }
+    assert_eq!(
+        RateBand::from("Ultra-Low Overnight".to_string()),
+        RateBand::UltraLowOvernight
+    );
+    assert_eq!(RateBand::from("TIER_1".to_string()), RateBand::Tier1);
+
+    for raw in ["TIERED", "Tiered"] {
+        assert_eq!(RatePlan::from(raw.to_string()), RatePlan::Tiered, "{raw}");
+    }
+    for raw in ["TOU", "TimeOfUse", "time-of-use"] {
+        assert_eq!(
+            RatePlan::from(raw.to_string()),
+            RatePlan::TimeOfUse,
+            "{raw}"
+        );
+    }
+    assert_eq!(
+        RatePlan::from("ultra_low_overnight".to_string()),
+        RatePlan::UltraLowOvernight
+    );
+}
+
+#[test]
+fn keeps_unknown_values() -> TestResult {
+    let band: RateBand = serde_json::from_value(json!("Super-Peak"))?;
+    assert_eq!(band, RateBand::Unknown("Super-Peak".to_string()));
+    assert_eq!(serde_json::to_value(&band)?, json!("Super-Peak"));
+
+    let plan: RatePlan = serde_json::from_value(json!("Flat Rate"))?;
+    assert_eq!(plan, RatePlan::Unknown("Flat Rate".to_string()));
+    assert_eq!(serde_json::to_value(&plan)?, json!("Flat Rate"));
+    Ok(())
+}
+
+#[test]
+fn writes_canonical_spelling() -> TestResult {
+    let band: RateBand = serde_json::from_value(json!("OFF_PEAK"))?;
+    assert_eq!(serde_json::to_value(&band)?, json!("OffPeak"));
+    assert_eq!(band.to_string(), "OffPeak");
+
+    let plan: RatePlan = serde_json::from_value(json!("TimeOfUse"))?;
+    assert_eq!(serde_json::to_value(&plan)?, json!("TOU"));
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use chrono::NaiveDate;
+use hydroottawa_api::{
+    api::HoApi,
+    auth::HoAuth,
+    client::HoClient,
+    error::Error,
+    recording::Recording,
+    source::{FileSource, LiveSource, MemorySource, UsageSource},
+    types::{HoHourlyUsage, HoProfile},
+};
+use hydroottawa_mock::{
+    ACCOUNT_ID, MockServer, PASSWORD, USERNAME, hourly_fixture, profile_fixture,
+};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+fn date(day: u32) -> Result<NaiveDate, &'static str> {
+    NaiveDate::from_ymd_opt(2025, 12, day).ok_or("invalid date")
+}
+
+// Every source answers the same for the same data
+async fn check_source(source: &impl UsageSource) -> TestResult {
+    let profile = source.profile().await?;
+    assert_eq!(profile.account_information.account_id, ACCOUNT_ID);
+
+    let days = source.hourly_days(vec![date(30)?, date(31)?]).await;
+    assert_eq!(days.len(), 2);
+    for (day, usage) in days {
+        let usage = usage?;
+        assert_eq!(usage.summary.actual_date, day);
+        assert_eq!(usage.intervals.len(), 24);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn live_source() -> TestResult {
+    let server = MockServer::start().await?;
+    let client = HoClient::builder()
+        .api_url(server.api_url())
+        .cognito_endpoint(server.cognito_endpoint())
+        .build()?;
+
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client);
+
+    check_source(&LiveSource::new(&api, &auth)).await
+}
+
+#[tokio::test]
+async fn file_source() -> TestResult {
+    let dir = tempfile::tempdir()?;
+
+    // Reads what a recording wrote
+    let server = MockServer::start().await?;
+    let client = HoClient::builder()
+        .api_url(server.api_url())
+        .cognito_endpoint(server.cognito_endpoint())
+        .build()?;
+    let auth = HoAuth::login(&client, USERNAME, PASSWORD).await?;
+    let api = HoApi::with_client(client).with_recording(Recording::Record(dir.path().into()));
+    check_source(&LiveSource::new(&api, &auth)).await?;
+    drop(server);
+
+    let source = FileSource::new(dir.path());
+    check_source(&source).await?;
+
+    let result = source.hourly(&date(29)?).await;
+    assert!(matches!(result, Err(Error::FixtureNotFound(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn memory_source() -> TestResult {
+    let profile: HoProfile = serde_json::from_value(profile_fixture())?;
+    let mut source = MemorySource::new(profile);
+    for day in [date(30)?, date(31)?] {
+        let usage: HoHourlyUsage = serde_json::from_value(hourly_fixture(day))?;
+        source = source.with_hourly(usage);
+    }
+
+    check_source(&source).await?;
+
+    let result = source.hourly(&date(29)?).await;
+    assert!(matches!(result, Err(Error::UsageNotFound(day)) if day == date(29)?));
+    Ok(())
+}
@@ -0,0 +1,226 @@
+//! Just enough of an MQTT 3.1.1 broker to test publishing: accepts any
+//! client, records its publishes and acks them unless told not to
+
+use std::{
+    collections::HashSet,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+const CONNECT: u8 = 1;
+const PUBLISH: u8 = 3;
+const PINGREQ: u8 = 12;
+const DISCONNECT: u8 = 14;
+
+const CONNACK: [u8; 4] = [0x20, 0x02, 0x00, 0x00];
+const PINGRESP: [u8; 2] = [0xd0, 0x00];
+
+/// Message received by `MockBroker`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Published {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+#[derive(Default)]
+struct BrokerState {
+    published: Vec<Published>,
+    unacked_topics: HashSet<String>,
+    connections: usize,
+    disconnects: usize,
+    last_will: Option<Published>,
+}
+
+type Shared = Arc<Mutex<BrokerState>>;
+
+fn lock(state: &Shared) -> MutexGuard<'_, BrokerState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// MQTT broker listening on a random local port, stopped when dropped
+pub struct MockBroker {
+    addr: SocketAddr,
+    state: Shared,
+    handle: JoinHandle<()>,
+}
+
+impl MockBroker {
+    pub async fn start() -> io::Result<Self> {
+        let state = Shared::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let shared = state.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = shared.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, &state).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// `host:port` to connect to
+    #[must_use]
+    pub fn address(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Never acknowledge publishes to `topic`, like a broker denying it
+    pub fn drop_acks(&self, topic: &str) {
+        lock(&self.state).unacked_topics.insert(topic.to_string());
+    }
+
+    /// Messages received so far, in order, including unacknowledged ones
+    #[must_use]
+    pub fn published(&self) -> Vec<Published> {
+        lock(&self.state).published.clone()
+    }
+
+    /// Number of clients that connected
+    #[must_use]
+    pub fn connections(&self) -> usize {
+        lock(&self.state).connections
+    }
+
+    /// Will of the latest client to connect
+    #[must_use]
+    pub fn last_will(&self) -> Option<Published> {
+        lock(&self.state).last_will.clone()
+    }
+
+    /// Number of clients that sent a DISCONNECT before closing
+    #[must_use]
+    pub fn disconnects(&self) -> usize {
+        lock(&self.state).disconnects
+    }
+}
+
+impl Drop for MockBroker {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// Fixed header packet type and flags, then the body
+async fn read_packet(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let header = stream.read_u8().await?;
+
+    let mut len = 0usize;
+    for shift in [0, 7, 14, 21] {
+        let byte = stream.read_u8().await?;
+        len |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    Ok((header, body))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Length prefixed field and what follows it
+fn split_field(bytes: &[u8]) -> io::Result<(&[u8], &[u8])> {
+    let [hi, lo, rest @ ..] = bytes else {
+        return Err(invalid("truncated field"));
+    };
+    rest.split_at_checked(usize::from(u16::from_be_bytes([*hi, *lo])))
+        .ok_or_else(|| invalid("truncated field"))
+}
+
+fn topic_name(bytes: &[u8]) -> io::Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid("topic isn't UTF-8"))
+}
+
+// Will message of a CONNECT, if the client set one
+fn parse_will(body: &[u8]) -> io::Result<Option<Published>> {
+    let (_protocol, rest) = split_field(body)?;
+    let [_level, flags, _keep_alive_hi, _keep_alive_lo, rest @ ..] = rest else {
+        return Err(invalid("truncated connect"));
+    };
+    if flags & 0x04 == 0 {
+        return Ok(None);
+    }
+
+    let (_client_id, rest) = split_field(rest)?;
+    let (topic, rest) = split_field(rest)?;
+    let (payload, _) = split_field(rest)?;
+    Ok(Some(Published {
+        topic: topic_name(topic)?,
+        payload: payload.to_vec(),
+        retain: flags & 0x20 != 0,
+    }))
+}
+
+// Topic, packet ID (0 for QoS 0) and payload
+fn parse_publish(flags: u8, body: &[u8]) -> io::Result<(String, u16, Vec<u8>)> {
+    let (topic, rest) = split_field(body)?;
+    let topic = topic_name(topic)?;
+
+    let qos = (flags >> 1) & 0x03;
+    if qos == 0 {
+        return Ok((topic, 0, rest.to_vec()));
+    }
+    let [hi, lo, payload @ ..] = rest else {
+        return Err(invalid("missing packet ID"));
+    };
+    Ok((topic, u16::from_be_bytes([*hi, *lo]), payload.to_vec()))
+}
+
+async fn serve(mut stream: TcpStream, state: &Shared) -> io::Result<()> {
+    loop {
+        let (header, body) = read_packet(&mut stream).await?;
+        let flags = header & 0x0f;
+
+        match header >> 4 {
+            CONNECT => {
+                let last_will = parse_will(&body)?;
+                {
+                    let mut state = lock(state);
+                    state.connections = state.connections.saturating_add(1);
+                    state.last_will = last_will;
+                }
+                stream.write_all(&CONNACK).await?;
+            }
+            PUBLISH => {
+                let (topic, pkid, payload) = parse_publish(flags, &body)?;
+                let ack = pkid != 0 && !lock(state).unacked_topics.contains(&topic);
+                lock(state).published.push(Published {
+                    topic,
+                    payload,
+                    retain: flags & 0x01 != 0,
+                });
+                if ack {
+                    let [hi, lo] = pkid.to_be_bytes();
+                    stream.write_all(&[0x40, 0x02, hi, lo]).await?;
+                }
+            }
+            PINGREQ => stream.write_all(&PINGRESP).await?,
+            DISCONNECT => {
+                let mut state = lock(state);
+                state.disconnects = state.disconnects.saturating_add(1);
+                return Ok(());
+            }
+            _ => return Err(invalid("unsupported packet")),
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! `InitiateAuth`, `RespondToAuthChallenge` and `ConfirmDevice` of the Cognito
+//! identity provider
+
+use axum::{
+    body::Bytes,
+    extract::State as AxumState,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use num_bigint::BigUint;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::{
+    CLIENT_ID, Device, MfaMethod, PendingChallenge, PendingLogin, Shared, State, lock, pool_name,
+    srp,
+};
+
+const TARGET_PREFIX: &str = "AWSCognitoIdentityProviderService.";
+const USER_ATTRIBUTES_PREFIX: &str = "userAttributes.";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InitiateAuthRequest {
+    auth_flow: String,
+    client_id: String,
+    #[serde(default)]
+    auth_parameters: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RespondToAuthChallengeRequest {
+    challenge_name: String,
+    client_id: String,
+    #[serde(default)]
+    challenge_responses: HashMap<String, String>,
+    session: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConfirmDeviceRequest {
+    access_token: String,
+    device_key: String,
+    device_secret_verifier_config: DeviceSecretVerifierConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeviceSecretVerifierConfig {
+    password_verifier: String,
+    salt: String,
+}
+
+/// Cognito style error, `kind` ends up in `__type`
+struct CognitoError {
+    status: StatusCode,
+    kind: &'static str,
+    message: String,
+}
+
+impl CognitoError {
+    fn new(kind: &'static str, message: &str) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    fn not_authorized() -> Self {
+        Self::new("NotAuthorizedException", "Incorrect username or password.")
+    }
+}
+
+impl IntoResponse for CognitoError {
+    fn into_response(self) -> Response {
+        let body = json!({ "__type": self.kind, "message": self.message });
+        amz_json(self.status, &body)
+    }
+}
+
+fn amz_json(status: StatusCode, body: &Value) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/x-amz-json-1.1")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+fn parse_srp_a(params: &HashMap<String, String>) -> Result<BigUint, CognitoError> {
+    let a_pub = param(params, "SRP_A")?;
+    BigUint::parse_bytes(a_pub.as_bytes(), 16)
+        .ok_or_else(|| CognitoError::new("InvalidParameterException", "Invalid SRP_A"))
+}
+
+fn param<'a>(params: &'a HashMap<String, String>, name: &str) -> Result<&'a str, CognitoError> {
+    params.get(name).map(String::as_str).ok_or_else(|| {
+        CognitoError::new(
+            "InvalidParameterException",
+            &format!("Missing required parameter {name}"),
+        )
+    })
+}
+
+fn check_client_id(client_id: &str) -> Result<(), CognitoError> {
+    if client_id == CLIENT_ID {
+        Ok(())
+    } else {
+        Err(CognitoError::new(
+            "ResourceNotFoundException",
+            "User pool client does not exist.",
+        ))
+    }
+}
+
+pub(crate) async fn handle(
+    AxumState(state): AxumState<Shared>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let target = headers
+        .get("x-amz-target")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(TARGET_PREFIX))
+        .unwrap_or_default();
+
+    let result = match target {
+        "InitiateAuth" => serde_json::from_slice(&body)
+            .map_err(|e| CognitoError::new("SerializationException", &e.to_string()))
+            .and_then(|request| initiate_auth(&mut lock(&state), &request)),
+        "RespondToAuthChallenge" => serde_json::from_slice(&body)
+            .map_err(|e| CognitoError::new("SerializationException", &e.to_string()))
+            .and_then(|request| respond_to_auth_challenge(&mut lock(&state), &request)),
+        "ConfirmDevice" => serde_json::from_slice(&body)
+            .map_err(|e| CognitoError::new("SerializationException", &e.to_string()))
+            .and_then(|request| confirm_device(&mut lock(&state), &request)),
+        _ => Err(CognitoError::new(
+            "UnknownOperationException",
+            &format!("Unknown target {target}"),
+        )),
+    };
+
+    match result {
+        Ok(body) => amz_json(StatusCode::OK, &body),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn initiate_auth(state: &mut State, request: &InitiateAuthRequest) -> Result<Value, CognitoError> {
+    check_client_id(&request.client_id)?;
+
+    match request.auth_flow.as_str() {
+        "USER_SRP_AUTH" => {
+            let username = param(&request.auth_parameters, "USERNAME")?;
+            let a_pub = parse_srp_a(&request.auth_parameters)?;
+
+            let verifier = state.users.get(username).ok_or_else(|| {
+                CognitoError::new("UserNotFoundException", "User does not exist.")
+            })?;
+            let exchange = srp::Exchange::new(verifier, a_pub)
+                .ok_or_else(|| CognitoError::new("InvalidParameterException", "Invalid SRP_A"))?;
+
+            let salt = hex::encode(&verifier.salt);
+            let srp_b = exchange.b_pub.to_str_radix(16);
+            let secret_block = BASE64.encode(srp::random_bytes(64));
+
+            state.pending.insert(
+                secret_block.clone(),
+                PendingLogin {
+                    username: username.to_string(),
+                    exchange,
+                    device_key: request.auth_parameters.get("DEVICE_KEY").cloned(),
+                },
+            );
+
+            Ok(json!({
+                "ChallengeName": "PASSWORD_VERIFIER",
+                "ChallengeParameters": {
+                    "SALT": salt,
+                    "SECRET_BLOCK": secret_block,
+                    "SRP_B": srp_b,
+                    "USERNAME": username,
+                    "USER_ID_FOR_SRP": username,
+                },
+            }))
+        }
+        "REFRESH_TOKEN_AUTH" | "REFRESH_TOKEN" => {
+            let refresh_token = param(&request.auth_parameters, "REFRESH_TOKEN")?;
+            let username = state
+                .tokens
+                .refresh
+                .get(refresh_token)
+                .cloned()
+                .ok_or_else(|| {
+                    CognitoError::new("NotAuthorizedException", "Invalid Refresh Token")
+                })?;
+
+            let (id_token, access_token) = state.issue(&username);
+            state.stats.refreshes = state.stats.refreshes.saturating_add(1);
+
+            Ok(json!({
+                "AuthenticationResult": {
+                    "AccessToken": access_token,
+                    "ExpiresIn": state.token_lifetime,
+                    "IdToken": id_token,
+                    "TokenType": "Bearer",
+                },
+                "ChallengeParameters": {},
+            }))
+        }
+        flow => Err(CognitoError::new(
+            "InvalidParameterException",
+            &format!("Unsupported auth flow {flow}"),
+        )),
+    }
+}
+
+fn respond_to_auth_challenge(
+    state: &mut State,
+    request: &RespondToAuthChallengeRequest,
+) -> Result<Value, CognitoError> {
+    check_client_id(&request.client_id)?;
+
+    match request.challenge_name.as_str() {
+        "PASSWORD_VERIFIER" => password_verifier(state, &request.challenge_responses),
+        "DEVICE_SRP_AUTH" => device_srp_auth(state, request),
+        "DEVICE_PASSWORD_VERIFIER" => device_password_verifier(state, request),
+        "SMS_MFA" | "SOFTWARE_TOKEN_MFA" => mfa(state, request),
+        "NEW_PASSWORD_REQUIRED" => new_password(state, request),
+        name => Err(CognitoError::new(
+            "InvalidParameterException",
+            &format!("Unsupported challenge {name}"),
+        )),
+    }
+}
+
+fn password_verifier(
+    state: &mut State,
+    responses: &HashMap<String, String>,
+) -> Result<Value, CognitoError> {
+    let secret_block = param(responses, "PASSWORD_CLAIM_SECRET_BLOCK")?;
+    let timestamp = param(responses, "TIMESTAMP")?;
+    let signature = param(responses, "PASSWORD_CLAIM_SIGNATURE")?;
+
+    // Each secret block is good for a single attempt
+    let pending = state
+        .pending
+        .remove(secret_block)
+        .ok_or_else(CognitoError::not_authorized)?;
+    if param(responses, "USERNAME")? != pending.username {
+        return Err(CognitoError::not_authorized());
+    }
+    if state.locked.contains(&pending.username) {
+        return Err(CognitoError::new(
+            "NotAuthorizedException",
+            "Password attempts exceeded",
+        ));
+    }
+
+    let verifier = state
+        .users
+        .get(&pending.username)
+        .ok_or_else(CognitoError::not_authorized)?;
+    let secret_block_bytes = BASE64
+        .decode(secret_block)
+        .map_err(|_| CognitoError::not_authorized())?;
+    let expected = pending.exchange.signature(
+        verifier,
+        pool_name(),
+        &pending.username,
+        &secret_block_bytes,
+        timestamp,
+    );
+
+    if signature != expected {
+        return Err(CognitoError::not_authorized());
+    }
+
+    if let Some(name) = state.forced_challenge.get(&pending.username) {
+        let name = name.clone();
+        return Ok(challenge(state, &pending.username, &name, &json!({})));
+    }
+
+    // A remembered device stands in for MFA
+    if let Some(device_key) = &pending.device_key
+        && state
+            .devices
+            .get(device_key)
+            .is_some_and(|device| device.username == pending.username && device.verifier.is_some())
+    {
+        return Ok(challenge(
+            state,
+            &pending.username,
+            "DEVICE_SRP_AUTH",
+            &json!({}),
+        ));
+    }
+
+    Ok(next_step(state, &pending.username))
+}
+
+fn device_srp_auth(
+    state: &mut State,
+    request: &RespondToAuthChallengeRequest,
+) -> Result<Value, CognitoError> {
+    let pending = take_session(state, request)?;
+    let device_key = param(&request.challenge_responses, "DEVICE_KEY")?;
+    let a_pub = parse_srp_a(&request.challenge_responses)?;
+
+    let verifier = state
+        .devices
+        .get(device_key)
+        .filter(|device| device.username == pending.username)
+        .and_then(|device| device.verifier.as_ref())
+        .ok_or_else(CognitoError::not_authorized)?;
+    let exchange = srp::Exchange::new(verifier, a_pub)
+        .ok_or_else(|| CognitoError::new("InvalidParameterException", "Invalid SRP_A"))?;
+
+    let salt = hex::encode(&verifier.salt);
+    let srp_b = exchange.b_pub.to_str_radix(16);
+    let secret_block = BASE64.encode(srp::random_bytes(64));
+
+    state.pending.insert(
+        secret_block.clone(),
+        PendingLogin {
+            username: pending.username.clone(),
+            exchange,
+            device_key: Some(device_key.to_string()),
+        },
+    );
+
+    let parameters = json!({
+        "SALT": salt,
+        "SECRET_BLOCK": secret_block,
+        "SRP_B": srp_b,
+        "USERNAME": pending.username,
+        "DEVICE_KEY": device_key,
+    });
+    Ok(challenge(
+        state,
+        &pending.username,
+        "DEVICE_PASSWORD_VERIFIER",
+        &parameters,
+    ))
+}
+
+fn device_password_verifier(
+    state: &mut State,
+    request: &RespondToAuthChallengeRequest,
+) -> Result<Value, CognitoError> {
+    let session = take_session(state, request)?;
+    let responses = &request.challenge_responses;
+    let secret_block = param(responses, "PASSWORD_CLAIM_SECRET_BLOCK")?;
+    let timestamp = param(responses, "TIMESTAMP")?;
+    let signature = param(responses, "PASSWORD_CLAIM_SIGNATURE")?;
+    let device_key = param(responses, "DEVICE_KEY")?;
+
+    let pending = state
+        .pending
+        .remove(secret_block)
+        .filter(|pending| pending.username == session.username)
+        .filter(|pending| pending.device_key.as_deref() == Some(device_key))
+        .ok_or_else(CognitoError::not_authorized)?;
+
+    let device = state
+        .devices
+        .get(device_key)
+        .ok_or_else(CognitoError::not_authorized)?;
+    let verifier = device
+        .verifier
+        .as_ref()
+        .ok_or_else(CognitoError::not_authorized)?;
+    let secret_block_bytes = BASE64
+        .decode(secret_block)
+        .map_err(|_| CognitoError::not_authorized())?;
+    let expected = pending.exchange.signature(
+        verifier,
+        &device.group_key,
+        device_key,
+        &secret_block_bytes,
+        timestamp,
+    );
+
+    if signature != expected {
+        return Err(CognitoError::not_authorized());
+    }
+
+    state.stats.device_logins = state.stats.device_logins.saturating_add(1);
+    Ok(authenticated(state, &pending.username, false))
+}
+
+fn confirm_device(
+    state: &mut State,
+    request: &ConfirmDeviceRequest,
+) -> Result<Value, CognitoError> {
+    if !state.tokens.access.contains(&request.access_token) {
+        return Err(CognitoError::new(
+            "NotAuthorizedException",
+            "Access Token has been revoked",
+        ));
+    }
+    if state.reject_devices {
+        return Err(CognitoError::new(
+            "InvalidParameterException",
+            "Device can't be confirmed",
+        ));
+    }
+
+    let config = &request.device_secret_verifier_config;
+    let (Ok(salt), Ok(v)) = (
+        BASE64.decode(&config.salt),
+        BASE64.decode(&config.password_verifier),
+    ) else {
+        return Err(CognitoError::new(
+            "InvalidParameterException",
+            "Invalid DeviceSecretVerifierConfig",
+        ));
+    };
+
+    let device = state
+        .devices
+        .get_mut(&request.device_key)
+        .ok_or_else(|| CognitoError::new("ResourceNotFoundException", "Device does not exist."))?;
+    device.verifier = Some(srp::Verifier::from_parts(salt, &v));
+
+    Ok(json!({ "UserConfirmationNecessary": false }))
+}
+
+// Challenges that may follow a verified password, in Cognito's order
+fn next_step(state: &mut State, username: &str) -> Value {
+    if let Some(required) = state.new_password.get(username) {
+        let required: Vec<_> = required
+            .iter()
+            .map(|name| format!("{USER_ATTRIBUTES_PREFIX}{name}"))
+            .collect();
+        let parameters = json!({
+            "userAttributes": json!({ "email": username }).to_string(),
+            "requiredAttributes": json!(required).to_string(),
+        });
+        return challenge(state, username, "NEW_PASSWORD_REQUIRED", &parameters);
+    }
+
+    if let Some((method, _)) = state.mfa.get(username) {
+        let method = *method;
+        let parameters = if method == MfaMethod::Sms {
+            json!({
+                "CODE_DELIVERY_DELIVERY_MEDIUM": "SMS",
+                "CODE_DELIVERY_DESTINATION": "+*******0100",
+            })
+        } else {
+            json!({})
+        };
+        return challenge(state, username, method.challenge_name(), &parameters);
+    }
+
+    authenticated(state, username, true)
+}
+
+fn challenge(state: &mut State, username: &str, name: &str, parameters: &Value) -> Value {
+    let session = BASE64.encode(srp::random_bytes(48));
+    state.sessions.insert(
+        session.clone(),
+        PendingChallenge {
+            username: username.to_string(),
+            challenge_name: name.to_string(),
+        },
+    );
+
+    let mut response = json!({
+        "Session": session,
+        "ChallengeParameters": parameters,
+    });
+    if !name.is_empty() {
+        response["ChallengeName"] = json!(name);
+    }
+    response
+}
+
+// The challenge `request` answers, each session is good for a single attempt
+fn take_session(
+    state: &mut State,
+    request: &RespondToAuthChallengeRequest,
+) -> Result<PendingChallenge, CognitoError> {
+    let session = request.session.as_deref().unwrap_or_default();
+
+    state
+        .sessions
+        .remove(session)
+        .filter(|pending| pending.challenge_name == request.challenge_name)
+        .filter(|pending| request.challenge_responses.get("USERNAME") == Some(&pending.username))
+        .ok_or_else(|| CognitoError::new("NotAuthorizedException", "Invalid session for the user."))
+}
+
+fn mfa(state: &mut State, request: &RespondToAuthChallengeRequest) -> Result<Value, CognitoError> {
+    let pending = take_session(state, request)?;
+
+    let code_key = format!("{}_CODE", request.challenge_name);
+    let code = param(&request.challenge_responses, &code_key)?;
+
+    let expected = state
+        .mfa
+        .get(&pending.username)
+        .map(|(_, code)| code.as_str());
+    if expected != Some(code) {
+        return Err(CognitoError::new(
+            "CodeMismatchException",
+            "Invalid code received for user",
+        ));
+    }
+
+    Ok(authenticated(state, &pending.username, true))
+}
+
+fn new_password(
+    state: &mut State,
+    request: &RespondToAuthChallengeRequest,
+) -> Result<Value, CognitoError> {
+    let pending = take_session(state, request)?;
+    let responses = &request.challenge_responses;
+    let password = param(responses, "NEW_PASSWORD")?;
+
+    let required = state
+        .new_password
+        .get(&pending.username)
+        .cloned()
+        .unwrap_or_default();
+    for name in required {
+        param(responses, &format!("{USER_ATTRIBUTES_PREFIX}{name}"))?;
+    }
+
+    state.add_user(&pending.username, password);
+    state.new_password.remove(&pending.username);
+
+    Ok(next_step(state, &pending.username))
+}
+
+// Final step of every successful login, `new_device` unless the login
+// already used a remembered one
+fn authenticated(state: &mut State, username: &str, new_device: bool) -> Value {
+    let (id_token, access_token) = state.issue(username);
+    let refresh_token = state.issue_refresh(username);
+    state.stats.logins = state.stats.logins.saturating_add(1);
+
+    let mut result = json!({
+        "AccessToken": access_token,
+        "ExpiresIn": state.token_lifetime,
+        "IdToken": id_token,
+        "RefreshToken": refresh_token,
+        "TokenType": "Bearer",
+    });
+
+    if new_device && state.remember_devices {
+        let device_key = format!("ca-central-1_{}", hex::encode(srp::random_bytes(16)));
+        let group_key = format!("-{}", hex::encode(srp::random_bytes(8)));
+        result["NewDeviceMetadata"] = json!({
+            "DeviceKey": device_key,
+            "DeviceGroupKey": group_key,
+        });
+        state.devices.insert(
+            device_key,
+            Device {
+                username: username.to_string(),
+                group_key,
+                verifier: None,
+            },
+        );
+    }
+
+    json!({
+        "AuthenticationResult": result,
+        "ChallengeParameters": {},
+    })
+}
@@ -0,0 +1,372 @@
+//! In-process stand-in for Cognito, the Hydro Ottawa API and an MQTT broker,
+//! so the client and CLI can be tested without network access or a real
+//! account
+
+mod broker;
+mod cognito;
+mod portal;
+mod srp;
+
+use axum::{
+    Router,
+    routing::{get, post},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL};
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::srp::{Exchange, Verifier};
+
+pub use crate::broker::{MockBroker, Published};
+pub use crate::portal::{hourly_fixture, profile_fixture};
+
+/// User registered by `MockServer::start`
+pub const USERNAME: &str = "user@example.com";
+pub const PASSWORD: &str = "correct horse battery staple";
+pub const ACCOUNT_ID: &str = "1234567890";
+
+/// Same as the production defaults so clients only need the URLs overridden
+pub const CLIENT_ID: &str = "7scfcis6ecucktmp4aqi1jk6cb";
+pub const USER_POOL_ID: &str = "ca-central-1_VYnwOhMBK";
+
+const DEFAULT_TOKEN_LIFETIME: i64 = 3600;
+
+/// Request counters, for asserting how the client behaved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub logins: usize,
+    pub refreshes: usize,
+    pub api_requests: usize,
+    pub rejected: usize,
+    pub device_logins: usize,
+}
+
+/// Second factor required by `MockServer::enable_mfa`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MfaMethod {
+    Sms,
+    SoftwareToken,
+}
+
+impl MfaMethod {
+    fn challenge_name(self) -> &'static str {
+        match self {
+            Self::Sms => "SMS_MFA",
+            Self::SoftwareToken => "SOFTWARE_TOKEN_MFA",
+        }
+    }
+}
+
+struct PendingLogin {
+    username: String,
+    exchange: Exchange,
+    // Offered in InitiateAuth, or being verified in DEVICE_SRP_AUTH
+    device_key: Option<String>,
+}
+
+// Device handed out in `NewDeviceMetadata`, remembered once confirmed
+struct Device {
+    username: String,
+    group_key: String,
+    verifier: Option<Verifier>,
+}
+
+// Password verified, waiting for the answer to `challenge_name`
+struct PendingChallenge {
+    username: String,
+    challenge_name: String,
+}
+
+#[derive(Default)]
+struct Tokens {
+    // ID token to username
+    id: HashMap<String, String>,
+    access: HashSet<String>,
+    jwt: HashSet<String>,
+    // Refresh token to username
+    refresh: HashMap<String, String>,
+}
+
+pub(crate) struct State {
+    users: HashMap<String, Verifier>,
+    mfa: HashMap<String, (MfaMethod, String)>,
+    // Username to the attributes required along with the new password
+    new_password: HashMap<String, Vec<String>>,
+    forced_challenge: HashMap<String, String>,
+    locked: HashSet<String>,
+    pending: HashMap<String, PendingLogin>,
+    sessions: HashMap<String, PendingChallenge>,
+    remember_devices: bool,
+    // ConfirmDevice fails instead of registering the device
+    reject_devices: bool,
+    // Device key to the device
+    devices: HashMap<String, Device>,
+    tokens: Tokens,
+    token_lifetime: i64,
+    // Status returned instead of the data, and how many more times
+    api_failure: Option<(u16, usize)>,
+    // Summary fields changed from the fixture, `None` removes the field
+    summary_patch: Vec<(String, Option<Value>)>,
+    // Same for the fields of every interval
+    interval_patch: Vec<(String, Option<Value>)>,
+    serial: u64,
+    stats: Stats,
+}
+
+type Shared = Arc<Mutex<State>>;
+
+fn pool_name() -> &'static str {
+    USER_POOL_ID
+        .split_once('_')
+        .map_or(USER_POOL_ID, |(_, name)| name)
+}
+
+// The client only cares about the shape, the signature is never checked
+fn jwt(claims: &Value) -> String {
+    let header = BASE64_URL.encode(json!({ "alg": "RS256", "typ": "JWT" }).to_string());
+    let payload = BASE64_URL.encode(claims.to_string());
+    let signature = BASE64_URL.encode(srp::random_bytes(32));
+
+    format!("{header}.{payload}.{signature}")
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            users: HashMap::new(),
+            mfa: HashMap::new(),
+            new_password: HashMap::new(),
+            forced_challenge: HashMap::new(),
+            locked: HashSet::new(),
+            pending: HashMap::new(),
+            sessions: HashMap::new(),
+            remember_devices: false,
+            reject_devices: false,
+            devices: HashMap::new(),
+            tokens: Tokens::default(),
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+            api_failure: None,
+            summary_patch: Vec::new(),
+            interval_patch: Vec::new(),
+            serial: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    fn add_user(&mut self, username: &str, password: &str) {
+        let verifier = Verifier::new(pool_name(), username, password);
+        self.users.insert(username.to_string(), verifier);
+    }
+
+    // Cognito ID and access tokens for `username`
+    fn issue(&mut self, username: &str) -> (String, String) {
+        self.serial = self.serial.saturating_add(1);
+
+        let iat = Utc::now().timestamp();
+        let exp = iat.saturating_add(self.token_lifetime);
+        let id = jwt(&json!({
+            "sub": username,
+            "cognito:username": username,
+            "email": username,
+            "token_use": "id",
+            "aud": CLIENT_ID,
+            "iat": iat,
+            "exp": exp,
+            "jti": self.serial,
+        }));
+        let access = jwt(&json!({
+            "sub": username,
+            "username": username,
+            "token_use": "access",
+            "client_id": CLIENT_ID,
+            "iat": iat,
+            "exp": exp,
+            "jti": self.serial,
+        }));
+
+        self.tokens.id.insert(id.clone(), username.to_string());
+        self.tokens.access.insert(access.clone());
+        (id, access)
+    }
+
+    fn issue_refresh(&mut self, username: &str) -> String {
+        let token = BASE64_URL.encode(srp::random_bytes(48));
+        self.tokens
+            .refresh
+            .insert(token.clone(), username.to_string());
+        token
+    }
+
+    // Hydro Ottawa JWT handed out by `/app-token`
+    fn issue_app_token(&mut self, username: &str) -> String {
+        self.serial = self.serial.saturating_add(1);
+
+        let iat = Utc::now().timestamp();
+        let token = jwt(&json!({
+            "sub": username,
+            "accountId": ACCOUNT_ID,
+            "iat": iat,
+            "exp": iat.saturating_add(self.token_lifetime),
+            "jti": self.serial,
+        }));
+
+        self.tokens.jwt.insert(token.clone());
+        token
+    }
+}
+
+/// Mock server listening on a random local port, stopped when dropped
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Shared,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start with the default `USERNAME` and `PASSWORD` user registered
+    pub async fn start() -> io::Result<Self> {
+        let mut state = State::new();
+        state.add_user(USERNAME, PASSWORD);
+        let state = Arc::new(Mutex::new(state));
+
+        let app = Router::new()
+            .route("/cognito/", post(cognito::handle))
+            .route("/app-token", get(portal::app_token))
+            .route("/profile", get(portal::profile))
+            .route("/usage/consumption/hourly", post(portal::hourly))
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Base URL of the Hydro Ottawa API
+    #[must_use]
+    pub fn api_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    #[must_use]
+    pub fn cognito_endpoint(&self) -> String {
+        format!("http://{}/cognito/", self.addr)
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    pub fn add_user(&self, username: &str, password: &str) {
+        self.state().add_user(username, password);
+    }
+
+    /// Require `code` as a second factor after the password of `username`
+    pub fn enable_mfa(&self, username: &str, method: MfaMethod, code: &str) {
+        self.state()
+            .mfa
+            .insert(username.to_string(), (method, code.to_string()));
+    }
+
+    /// Ask `username` for a new password, and `required_attributes`, after
+    /// the temporary one
+    pub fn require_new_password(&self, username: &str, required_attributes: &[&str]) {
+        let required = required_attributes
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        self.state()
+            .new_password
+            .insert(username.to_string(), required);
+    }
+
+    /// Answer the password of `username` with the challenge `name`, for
+    /// testing challenges the client doesn't support, an empty `name` leaves
+    /// out `ChallengeName` altogether
+    pub fn force_challenge(&self, username: &str, name: &str) {
+        self.state()
+            .forced_challenge
+            .insert(username.to_string(), name.to_string());
+    }
+
+    /// Offer a new device at the end of every login, a confirmed device
+    /// answers `DEVICE_SRP_AUTH` instead of MFA on later logins
+    pub fn remember_devices(&self) {
+        self.state().remember_devices = true;
+    }
+
+    /// Fail every `ConfirmDevice` request, the offered devices are never
+    /// remembered
+    pub fn reject_devices(&self) {
+        self.state().reject_devices = true;
+    }
+
+    /// Reject every password of `username` like Cognito does after too many
+    /// failed attempts
+    pub fn lock_account(&self, username: &str) {
+        self.state().locked.insert(username.to_string());
+    }
+
+    /// Invalidate every issued ID, access and app token, refresh tokens stay
+    /// valid so the client can recover
+    pub fn revoke_tokens(&self) {
+        let tokens = &mut self.state().tokens;
+        tokens.id.clear();
+        tokens.access.clear();
+        tokens.jwt.clear();
+    }
+
+    /// Answer the next `times` profile and usage requests with `status`, 429
+    /// responses carry a one second `Retry-After`
+    pub fn fail_api(&self, status: u16, times: usize) {
+        self.state().api_failure = Some((status, times));
+    }
+
+    /// Serve `value` as the usage summary field `name`, or leave the field out
+    /// for `None`, like a portal change would
+    pub fn patch_summary(&self, name: &str, value: Option<Value>) {
+        self.state().summary_patch.push((name.to_string(), value));
+    }
+
+    /// Like `patch_summary`, for the field `name` of every hourly interval
+    pub fn patch_intervals(&self, name: &str, value: Option<Value>) {
+        self.state().interval_patch.push((name.to_string(), value));
+    }
+
+    /// `ExpiresIn` reported for tokens issued from now on
+    pub fn set_token_lifetime(&self, seconds: i64) {
+        self.state().token_lifetime = seconds;
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.state().stats
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// A panicking handler shouldn't take the remaining tests down with it
+fn lock(state: &Shared) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
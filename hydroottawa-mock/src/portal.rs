@@ -0,0 +1,193 @@
+//! Hydro Ottawa account API, served from the fixtures
+
+use axum::{
+    Json,
+    extract::State as AxumState,
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, RETRY_AFTER},
+    },
+    response::{IntoResponse, Response},
+};
+use chrono::{NaiveDate, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::America::Toronto;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{Shared, State, lock};
+
+const PROFILE: &str = include_str!("../fixtures/profile.json");
+const HOURLY: &str = include_str!("../fixtures/hourly.json");
+
+const LOCAL_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HourlyRequest {
+    date: NaiveDate,
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+fn unauthorized(state: &mut State) -> Response {
+    state.stats.rejected = state.stats.rejected.saturating_add(1);
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({ "message": "Unauthorized" })),
+    )
+        .into_response()
+}
+
+// Every API call carries the app JWT as well as the Cognito tokens
+fn authorized(state: &State, headers: &HeaderMap) -> bool {
+    let jwt = header(headers, AUTHORIZATION.as_str())
+        .strip_prefix("Bearer ")
+        .unwrap_or_default();
+
+    state.tokens.jwt.contains(jwt)
+        && state.tokens.id.contains_key(header(headers, "x-id"))
+        && state.tokens.access.contains(header(headers, "x-access"))
+}
+
+// API Gateway hands back the Authorization the backend saw under this name
+fn remapped_authorization(headers: &HeaderMap) -> [(&'static str, String); 1] {
+    [(
+        "x-amzn-remapped-authorization",
+        header(headers, AUTHORIZATION.as_str()).to_string(),
+    )]
+}
+
+// Failure set up by `MockServer::fail_api`
+fn injected_failure(state: &mut State) -> Option<Response> {
+    let (status, times) = state.api_failure.as_mut()?;
+    let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    *times = times.saturating_sub(1);
+    if *times == 0 {
+        state.api_failure = None;
+    }
+    state.stats.api_requests = state.stats.api_requests.saturating_add(1);
+
+    let body = Json(json!({ "message": status.canonical_reason() }));
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        Some((status, [(RETRY_AFTER, "1")], body).into_response())
+    } else {
+        Some((status, body).into_response())
+    }
+}
+
+pub(crate) async fn app_token(AxumState(state): AxumState<Shared>, headers: HeaderMap) -> Response {
+    let mut state = lock(&state);
+
+    let username = state.tokens.id.get(header(&headers, "x-id")).cloned();
+    let username = match username {
+        Some(username) if state.tokens.access.contains(header(&headers, "x-access")) => username,
+        _ => return unauthorized(&mut state),
+    };
+
+    let jwt = state.issue_app_token(&username);
+    (
+        [("x-amzn-remapped-authorization", format!("Bearer {jwt}"))],
+        Json(json!({})),
+    )
+        .into_response()
+}
+
+pub(crate) async fn profile(AxumState(state): AxumState<Shared>, headers: HeaderMap) -> Response {
+    let mut state = lock(&state);
+    if !authorized(&state, &headers) {
+        return unauthorized(&mut state);
+    }
+    if let Some(response) = injected_failure(&mut state) {
+        return response;
+    }
+    state.stats.api_requests = state.stats.api_requests.saturating_add(1);
+
+    (remapped_authorization(&headers), Json(profile_fixture())).into_response()
+}
+
+fn patch(object: &mut Map<String, Value>, fields: &[(String, Option<Value>)]) {
+    for (name, value) in fields {
+        match value {
+            Some(value) => object.insert(name.clone(), value.clone()),
+            None => object.remove(name),
+        };
+    }
+}
+
+pub(crate) async fn hourly(
+    AxumState(state): AxumState<Shared>,
+    headers: HeaderMap,
+    Json(request): Json<HourlyRequest>,
+) -> Response {
+    let mut state = lock(&state);
+    if !authorized(&state, &headers) {
+        return unauthorized(&mut state);
+    }
+    if let Some(response) = injected_failure(&mut state) {
+        return response;
+    }
+    state.stats.api_requests = state.stats.api_requests.saturating_add(1);
+
+    let mut usage = hourly_fixture(request.date);
+    if let Some(summary) = usage["summary"].as_object_mut() {
+        patch(summary, &state.summary_patch);
+    }
+    if let Some(intervals) = usage["intervals"].as_array_mut() {
+        for interval in intervals.iter_mut().filter_map(Value::as_object_mut) {
+            patch(interval, &state.interval_patch);
+        }
+    }
+
+    (remapped_authorization(&headers), Json(usage)).into_response()
+}
+
+/// The profile `/profile` answers with
+#[must_use]
+pub fn profile_fixture() -> Value {
+    serde_json::from_str(PROFILE).unwrap_or_default()
+}
+
+/// The usage the hourly endpoint answers with for `date`, the fixture day
+/// moved to `date` with one interval per hour. Like the portal, intervals
+/// carry local wall clock times, so the day has 23 or 25 of them when
+/// daylight saving time starts or ends.
+#[must_use]
+pub fn hourly_fixture(date: NaiveDate) -> Value {
+    let mut usage: Value = serde_json::from_str(HOURLY).unwrap_or_default();
+    let fixture = usage["intervals"].as_array().cloned().unwrap_or_default();
+
+    let hour = TimeDelta::hours(1);
+    let mut start = Toronto
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest();
+    let mut intervals = Vec::new();
+
+    for interval in fixture.iter().cycle() {
+        let Some(local) = start
+            .map(|start| start.naive_local())
+            .filter(|local| local.date() == date)
+        else {
+            break;
+        };
+        let Some(end) = local.checked_add_signed(hour) else {
+            break;
+        };
+
+        let mut interval = interval.clone();
+        interval["startDateTime"] = json!(local.format(LOCAL_FORMAT).to_string());
+        interval["endDateTime"] = json!(end.format(LOCAL_FORMAT).to_string());
+        intervals.push(interval);
+        start = start.and_then(|start| start.checked_add_signed(hour));
+    }
+
+    usage["summary"]["numberOfHours"] = json!(intervals.len());
+    usage["intervals"] = json!(intervals);
+    usage["summary"]["actualDate"] = json!(date.format("%Y-%m-%d").to_string());
+
+    usage
+}
@@ -0,0 +1,152 @@
+//! Server side of the Cognito SRP exchange, the mirror image of what
+//! `aws-cognito-srp` computes on the client
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const N_HEX: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD1",
+    "29024E088A67CC74020BBEA63B139B22514A08798E3404DD",
+    "EF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245",
+    "E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3D",
+    "C2007CB8A163BF0598DA48361C55D39A69163FA8FD24CF5F",
+    "83655D23DCA3AD961C62F356208552BB9ED529077096966D",
+    "670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9",
+    "DE2BCBF6955817183995497CEA956AE515D2261898FA0510",
+    "15728E5A8AAAC42DAD33170D04507A33A85521ABDF1CBA64",
+    "ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7",
+    "ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6B",
+    "F12FFA06D98A0864D87602733EC86A64521F2B18177B200C",
+    "BBE117577A615D6C770988C0BAD946E208E24FA074E5AB31",
+    "43DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF"
+);
+
+const DERIVE_KEY_INFO: &[u8] = b"Caldera Derived Key\x01";
+
+static N: LazyLock<BigUint> =
+    LazyLock::new(|| BigUint::parse_bytes(N_HEX.as_bytes(), 16).unwrap_or_default());
+static G: LazyLock<BigUint> = LazyLock::new(|| BigUint::from(2u32));
+
+// k = H(0 | N | g) as computed by the client
+static K: LazyLock<BigUint> = LazyLock::new(|| {
+    let mut digest = Sha256::new();
+    digest.update([0]);
+    digest.update(N.to_bytes_be());
+    digest.update(G.to_bytes_be());
+    BigUint::from_bytes_be(&digest.finalize())
+});
+
+// Cognito prefixes values with a zero byte when the high bit is set
+fn left_pad(data: &[u8]) -> Vec<u8> {
+    match data.first() {
+        Some(first) if *first >= 128 => [&[0], data].concat(),
+        _ => data.to_vec(),
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap_or_else(|_| unreachable!());
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// What Cognito stores for a user instead of the password
+pub struct Verifier {
+    pub salt: Vec<u8>,
+    v: BigUint,
+}
+
+impl Verifier {
+    pub fn new(pool_name: &str, user_id: &str, password: &str) -> Self {
+        let salt = random_bytes(16);
+
+        let mut identity = Sha256::new();
+        identity.update(pool_name.as_bytes());
+        identity.update(user_id.as_bytes());
+        identity.update(b":");
+        identity.update(password.as_bytes());
+
+        let mut x = Sha256::new();
+        x.update(left_pad(&salt));
+        x.update(identity.finalize());
+        let x = BigUint::from_bytes_be(&x.finalize());
+
+        let v = G.modpow(&x, &N);
+        Self { salt, v }
+    }
+
+    /// Verifier the client computed itself, as for `ConfirmDevice`
+    pub fn from_parts(salt: Vec<u8>, v: &[u8]) -> Self {
+        Self {
+            salt,
+            v: BigUint::from_bytes_be(v),
+        }
+    }
+}
+
+/// Server half of one login attempt
+pub struct Exchange {
+    a_pub: BigUint,
+    b: BigUint,
+    pub b_pub: BigUint,
+}
+
+impl Exchange {
+    /// `None` when the client's `A` is degenerate
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn new(verifier: &Verifier, a_pub: BigUint) -> Option<Self> {
+        if (&a_pub % &*N) == BigUint::ZERO {
+            return None;
+        }
+
+        let b = BigUint::from_bytes_be(&random_bytes(128));
+        let b_pub = (&*K * &verifier.v + G.modpow(&b, &N)) % &*N;
+
+        Some(Self { a_pub, b, b_pub })
+    }
+
+    /// Expected `PASSWORD_CLAIM_SIGNATURE`
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn signature(
+        &self,
+        verifier: &Verifier,
+        pool_name: &str,
+        user_id: &str,
+        secret_block: &[u8],
+        timestamp: &str,
+    ) -> String {
+        let mut u = Sha256::new();
+        u.update(left_pad(&self.a_pub.to_bytes_be()));
+        u.update(left_pad(&self.b_pub.to_bytes_be()));
+        let u = BigUint::from_bytes_be(&u.finalize());
+
+        let s = (&self.a_pub * verifier.v.modpow(&u, &N)).modpow(&self.b, &N);
+
+        let prk = hmac(&left_pad(&u.to_bytes_be()), &left_pad(&s.to_bytes_be()));
+        let key = &hmac(&prk, DERIVE_KEY_INFO)[..16];
+
+        let msg = [
+            pool_name.as_bytes(),
+            user_id.as_bytes(),
+            secret_block,
+            timestamp.as_bytes(),
+        ]
+        .concat();
+
+        BASE64.encode(hmac(key, &msg))
+    }
+}
@@ -0,0 +1,121 @@
+//! Where the login password comes from
+
+use anyhow::{Context, Result, bail};
+use dialoguer::Password;
+use hydroottawa_api::secret::Secret;
+use log::debug;
+use std::{
+    env, fs,
+    io::{self, BufRead},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// Name of the systemd credential holding the password, e.g.
+/// `LoadCredential=password:/etc/hydroottawa/password`
+pub const SYSTEMD_CREDENTIAL: &str = "password";
+
+/// Source of the login password
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordProvider {
+    /// Standard output of a shell command, e.g. `pass show hydro`
+    Command(String),
+    /// Contents of a file
+    File(PathBuf),
+    /// First line of standard input
+    Stdin,
+    /// The `password` credential of a systemd service
+    SystemdCredential,
+    /// `HO_PASSWORD`
+    Env,
+    /// Ask on the terminal
+    Prompt,
+}
+
+// Drops a single trailing line break, in place so no copy is left behind
+fn strip_newline(mut password: String) -> String {
+    if password.ends_with('\n') {
+        password.pop();
+        if password.ends_with('\r') {
+            password.pop();
+        }
+    }
+    password
+}
+
+impl PasswordProvider {
+    /// Password from this provider, `None` when it has nothing to give, e.g.
+    /// an unset variable
+    pub fn password(&self, username: &str) -> Result<Option<Secret>> {
+        let password = match self {
+            Self::Command(command) => {
+                let output = Command::new("sh")
+                    .args(["-c", command])
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .with_context(|| format!("Unable to run the password command `{command}`"))?;
+                if !output.status.success() {
+                    bail!("The password command `{command}` failed: {}", output.status);
+                }
+                String::from_utf8(output.stdout).context("The password isn't UTF-8")?
+            }
+            Self::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("Unable to read the password from {}", path.display()))?,
+            Self::Stdin => {
+                let mut line = String::new();
+                io::stdin().lock().read_line(&mut line)?;
+                line
+            }
+            Self::SystemdCredential => {
+                let Some(dir) = env::var_os("CREDENTIALS_DIRECTORY") else {
+                    return Ok(None);
+                };
+                let path = PathBuf::from(dir).join(SYSTEMD_CREDENTIAL);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                fs::read_to_string(&path)
+                    .with_context(|| format!("Unable to read the credential {}", path.display()))?
+            }
+            Self::Env => match env::var("HO_PASSWORD") {
+                Ok(password) => password,
+                Err(_) => return Ok(None),
+            },
+            Self::Prompt => Password::new()
+                .with_prompt(format!("Password for {username}"))
+                .interact()?,
+        };
+
+        let password = strip_newline(password);
+        if password.is_empty() {
+            bail!("Empty password from {self:?}");
+        }
+        Ok(Some(password.into()))
+    }
+}
+
+/// Only `explicit` when given, otherwise the systemd credential, the
+/// environment and finally the prompt
+#[must_use]
+pub fn providers(explicit: Option<PasswordProvider>) -> Vec<PasswordProvider> {
+    match explicit {
+        Some(provider) => vec![provider],
+        None => vec![
+            PasswordProvider::SystemdCredential,
+            PasswordProvider::Env,
+            PasswordProvider::Prompt,
+        ],
+    }
+}
+
+/// Password from the first of `providers` that has one
+pub fn password(providers: &[PasswordProvider], username: &str) -> Result<Secret> {
+    for provider in providers {
+        if let Some(password) = provider.password(username)? {
+            debug!("Password from {provider:?}");
+            return Ok(password);
+        }
+    }
+    bail!("No password available for {username}")
+}
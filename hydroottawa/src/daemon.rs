@@ -0,0 +1,247 @@
+use anyhow::Result;
+use chrono::Local;
+use hydroottawa_api::{
+    auth::HoAuth, drift::SchemaDrift, error::Error as ApiError, source::UsageSource,
+    types::HoProfile,
+};
+use log::{debug, error, info, warn};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::{future::Future, time::Duration};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    mqtt_pub::{
+        MqttTopics, OFFLINE, ONLINE, SENSORS, availability_message, last_will, publish_usage,
+        state_payload,
+    },
+    publisher::Message,
+    token_store::TokenStore,
+};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// Room for a poll's messages besides the online announcement, offline and
+// the disconnect, the first poll queues before the broker even answers
+const QUEUE_CAPACITY: usize = SENSORS.len().saturating_add(4);
+
+/// Polls a usage source and publishes to a single long lived MQTT connection
+pub struct Daemon<'a, S> {
+    source: &'a S,
+    // Where the refreshed session is saved after each poll
+    session_cache: Option<(&'a TokenStore, &'a HoAuth)>,
+    interval: Duration,
+    profile: HoProfile,
+    topics: MqttTopics,
+    last_state: Option<String>,
+    reported_drift: SchemaDrift,
+}
+
+// Drives the MQTT connection until the client disconnects, rumqttc
+// reconnects on the next poll after an error. Every connection announces
+// itself online again, as a dropped one set the availability to the will.
+fn spawn_eventloop(
+    mut eventloop: rumqttc::EventLoop,
+    client: AsyncClient,
+    online: Message,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut disconnecting = false;
+
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker");
+                    // Can't wait for room in the queue, only this task drains it
+                    if let Err(e) = client.try_publish(
+                        &online.topic,
+                        QoS::AtLeastOnce,
+                        online.retain,
+                        online.payload.clone(),
+                    ) {
+                        warn!("Unable to announce availability: {e}");
+                    }
+                }
+                // Keep reading until the broker closes the connection, closing
+                // it with acks unread resets it and loses the DISCONNECT
+                Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
+                    disconnecting = true;
+                }
+                Ok(event) => {
+                    debug!("MQTT event: {event:?}");
+                }
+                Err(_) if disconnecting => {
+                    debug!("Disconnected from MQTT broker");
+                    break;
+                }
+                Err(e) => {
+                    warn!("MQTT connection error: {e}");
+                    sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    })
+}
+
+// Failures only a fresh login fixes, the session or the account itself was
+// rejected and retrying can't change that
+fn needs_login(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<ApiError>(),
+        Some(ApiError::TokenExpired | ApiError::InvalidCredentials(_) | ApiError::AccountLocked)
+    )
+}
+
+impl<'a, S: UsageSource> Daemon<'a, S> {
+    #[must_use]
+    pub fn new(source: &'a S, profile: HoProfile, interval: Duration) -> Self {
+        Self {
+            source,
+            session_cache: None,
+            interval,
+            profile,
+            topics: MqttTopics::default(),
+            last_state: None,
+            reported_drift: SchemaDrift::default(),
+        }
+    }
+
+    /// Topics the usage is published to
+    #[must_use]
+    pub fn with_topics(mut self, topics: MqttTopics) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Save the session of `auth` to `store` after each poll, so a restart
+    /// picks up the refreshed tokens
+    #[must_use]
+    pub fn with_session_cache(mut self, store: &'a TokenStore, auth: &'a HoAuth) -> Self {
+        self.session_cache = Some((store, auth));
+        self
+    }
+
+    /// Runs until SIGTERM or Ctrl-C
+    pub async fn run(self, mqttoptions: MqttOptions) -> Result<()> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let shutdown = async move {
+            tokio::select! {
+                _ = sigterm.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        };
+        self.run_until(mqttoptions, shutdown).await
+    }
+
+    /// Runs until `shutdown` completes, or fails once the session can't be
+    /// used anymore so a service manager restarts it to log in again
+    pub async fn run_until(
+        mut self,
+        mut mqttoptions: MqttOptions,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let account_id = self.profile.account_information.account_id.clone();
+        mqttoptions.set_last_will(last_will(&self.topics, &account_id));
+        let (client, eventloop) = AsyncClient::new(mqttoptions, QUEUE_CAPACITY);
+        let eventloop_handle = spawn_eventloop(
+            eventloop,
+            client.clone(),
+            availability_message(&self.topics, &account_id, ONLINE),
+        );
+
+        tokio::pin!(shutdown);
+        let mut backoff = MIN_BACKOFF;
+        let mut result = Ok(());
+
+        info!("Polling every {} minutes", self.interval.as_secs() / 60);
+
+        loop {
+            let delay = tokio::select! {
+                res = self.poll(&client) => match res {
+                    Ok(()) => {
+                        backoff = MIN_BACKOFF;
+                        self.interval
+                    }
+                    Err(e) if needs_login(&e) => {
+                        result = Err(e.context("Unable to keep polling"));
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Poll failed, retrying in {}s: {e}", backoff.as_secs());
+                        let delay = backoff;
+                        backoff = backoff.saturating_mul(2).min(self.interval);
+                        delay
+                    }
+                },
+                () = &mut shutdown => break,
+            };
+
+            tokio::select! {
+                () = sleep(delay) => {}
+                () = &mut shutdown => break,
+            }
+        }
+
+        info!("Shutting down");
+
+        // Don't hang on a broker that went away. A clean disconnect drops the
+        // will, so go offline explicitly.
+        let offline = availability_message(&self.topics, &account_id, OFFLINE);
+        let disconnect = async {
+            client
+                .publish(
+                    offline.topic,
+                    QoS::AtLeastOnce,
+                    offline.retain,
+                    offline.payload,
+                )
+                .await?;
+            client.disconnect().await?;
+            eventloop_handle.await?;
+            anyhow::Ok(())
+        };
+
+        match timeout(SHUTDOWN_TIMEOUT, disconnect).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Unable to disconnect from the MQTT broker: {e}"),
+            Err(_) => warn!("Timed out disconnecting from the MQTT broker"),
+        }
+        result
+    }
+
+    async fn poll(&mut self, client: &AsyncClient) -> Result<()> {
+        // Usage is posted with a delay, keep checking the previous day
+        let today = Local::now().date_naive();
+        let date = today.pred_opt().unwrap_or(today);
+
+        let usage = self.source.hourly(&date).await?;
+
+        // Only warn again when the portal changes something else
+        let drift = self.source.drift();
+        if drift != self.reported_drift {
+            warn!("The API response changed: {drift}");
+            self.reported_drift = drift;
+        }
+
+        if let Some((store, auth)) = self.session_cache
+            && let Err(e) = store.save(&auth.session().await)
+        {
+            warn!("Unable to cache the session: {e}");
+        }
+
+        let state = state_payload(&usage).to_string();
+
+        if self.last_state.as_ref() == Some(&state) {
+            debug!("Usage for {date} unchanged, not publishing");
+            return Ok(());
+        }
+
+        publish_usage(client, &self.topics, &self.profile, &usage).await?;
+        self.last_state = Some(state);
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
-use hydroottawa_api::types::{HoHourlyUsage, HoProfile};
+use hydroottawa_api::{
+    datetime::HoDateTime,
+    types::{HoHourlyUsage, HoProfile},
+};
 use std::fmt;
 use tabled::Table;
 
+const DATE_FORMAT: &str = "%Y-%m-%d";
+// The zone abbreviation tells the repeated hour apart on DST days
+const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+
 pub struct ProfileDisplay<'a>(pub &'a HoProfile);
 pub struct UsageDisplay<'a>(pub &'a HoHourlyUsage);
 
@@ -128,10 +135,17 @@ impl fmt::Display for UsageDisplay<'_> {
         writeln!(f, "\n=== Hourly Usage Summary ===")?;
         writeln!(f, "Date: {}", usage.summary.actual_date)?;
         writeln!(f, "Rate Plan: {}", usage.summary.rate_plan)?;
+        let billing_date = |date: Option<HoDateTime>| {
+            date.map_or_else(
+                || "?".to_string(),
+                |date| date.format(DATE_FORMAT).to_string(),
+            )
+        };
         writeln!(
             f,
             "Billing Period: {} to {}",
-            usage.summary.billing_period_start_date, usage.summary.billing_period_end_date
+            billing_date(usage.summary.billing_period_start_date),
+            billing_date(usage.summary.billing_period_end_date)
         )?;
         writeln!(f, "\n--- Overall Statistics ---")?;
         writeln!(f, "Total Usage: {:.2} kWh", usage.summary.total_usage)?;
@@ -177,9 +191,9 @@ impl fmt::Display for UsageDisplay<'_> {
             .intervals
             .iter()
             .map(|i| IntervalDisplay {
-                start_date_time: i.start_date_time.clone(),
-                end_date_time: i.end_date_time.clone(),
-                rate_band: i.rate_band.clone(),
+                start_date_time: i.start_date_time.format(DATE_TIME_FORMAT).to_string(),
+                end_date_time: i.end_date_time.format(DATE_TIME_FORMAT).to_string(),
+                rate_band: i.rate_band.to_string(),
                 hourly_usage: format!("{:.2}", i.hourly_usage),
                 hourly_cost: format!("{:.2}", i.hourly_cost),
             })
@@ -1,2 +1,9 @@
-pub mod mqtt_pub;
+pub mod credentials;
+pub mod daemon;
 pub mod display;
+pub mod mqtt_pub;
+pub mod output;
+pub mod publisher;
+pub mod storage;
+pub mod token_store;
+pub mod xdg;
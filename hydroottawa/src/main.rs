@@ -1,16 +1,30 @@
-mod display;
-
 use anyhow::Result;
 use chrono::{Local, NaiveDate};
-use clap::Parser;
-use dialoguer::Password;
-use hydroottawa::mqtt_pub::mqtt_publish;
-use hydroottawa_api::{api::HoApi, auth::HoAuth};
-use log::LevelFilter;
+use clap::{Parser, Subcommand};
+use dialoguer::{Input, Password};
+use hydroottawa::{
+    credentials::{self, PasswordProvider},
+    daemon::Daemon,
+    mqtt_pub::{MqttTls, MqttTopics, default_client_id, mqtt_options, mqtt_publish},
+    output::{OutputFormat, print_usage},
+    storage::UsageStore,
+    token_store::TokenStore,
+};
+use hydroottawa_api::{
+    api::HoApi,
+    auth::{Challenge, ChallengeAnswer, HoAuth, HoDevice, HoSession, LoginStep, MfaKind},
+    client::HoClient,
+    error::Error as ApiError,
+    recording::Recording,
+    retry::RetryPolicy,
+    secret::Secret,
+    source::{LiveSource, UsageSource},
+    types::HoProfile,
+};
+use log::{LevelFilter, error, info, warn};
 use rstaples::logging::StaplesLogger;
-use std::env;
-
-use display::{ProfileDisplay, UsageDisplay};
+use rumqttc::MqttOptions;
+use std::{collections::HashMap, env, path::PathBuf, time::Duration};
 
 const FALLBACK_DATE: NaiveDate = match NaiveDate::from_ymd_opt(2025, 1, 1) {
     Some(date) => date,
@@ -32,26 +46,350 @@ struct UserArgs {
     verbose: bool,
 
     /// date
-    #[arg(short, long, default_value_t = yesterday())]
+    #[arg(short, long, default_value_t = yesterday(), conflicts_with = "from")]
     date: NaiveDate,
 
+    /// First day of a date range
+    #[arg(long, conflicts_with = "mqtt")]
+    from: Option<NaiveDate>,
+
+    /// Last day of a date range
+    #[arg(long, requires = "from", default_value_t = yesterday())]
+    to: NaiveDate,
+
     /// Username
     #[arg(short, long)]
     username: String,
 
-    /// Username
+    /// MQTT broker, `mqtt://[user:pass@]host[:port]`, `mqtts://...`,
+    /// `ws://...[/path]`, `wss://...` or a bare `host[:port]`
     #[arg(short, long)]
     mqtt: Option<String>,
+
+    /// CA bundle (PEM) for an mqtts:// or wss:// broker, the system roots by default
+    #[arg(long, requires = "mqtt")]
+    mqtt_ca: Option<PathBuf>,
+
+    /// Client certificate (PEM) for an mqtts:// or wss:// broker
+    #[arg(long, requires_all = ["mqtt", "mqtt_key"])]
+    mqtt_cert: Option<PathBuf>,
+
+    /// Client certificate key (PEM) for an mqtts:// or wss:// broker
+    #[arg(long, requires_all = ["mqtt", "mqtt_cert"])]
+    mqtt_key: Option<PathBuf>,
+
+    /// MQTT client ID, unique per run by default
+    #[arg(long, requires = "mqtt")]
+    mqtt_client_id: Option<String>,
+
+    /// Topic prefix of the usage state, `<prefix>/<account>/state`
+    #[arg(long, default_value_t = MqttTopics::default().base_topic)]
+    mqtt_base_topic: String,
+
+    /// Home Assistant MQTT discovery prefix
+    #[arg(long, default_value_t = MqttTopics::default().discovery_prefix)]
+    mqtt_discovery_prefix: String,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// Run this shell command and use its output as the password
+    #[arg(long, group = "password_source")]
+    password_command: Option<String>,
+
+    /// Read the password from this file
+    #[arg(long, group = "password_source")]
+    password_file: Option<PathBuf>,
+
+    /// Read the password from the first line of standard input
+    #[arg(long, group = "password_source")]
+    password_stdin: bool,
+
+    /// Always log in with the password instead of the cached session
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Retries of requests failing with connection, throttling or server errors
+    #[arg(long, default_value_t = RetryPolicy::default().max_retries)]
+    retries: u32,
+
+    /// Write the raw API requests and responses to fixture files in this
+    /// directory, auth headers redacted
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer from the fixture files written by --record, without network
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Proxy URL for the Hydro Ottawa and Cognito requests
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Hydro Ottawa API base URL
+    #[arg(long, hide = true)]
+    api_url: Option<String>,
+
+    /// Cognito identity provider endpoint
+    #[arg(long, hide = true)]
+    cognito_endpoint: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn get_password(username: &str) -> Result<String> {
-    if let Ok(password) = env::var("HO_PASSWORD") {
-        Ok(password)
+#[derive(Subcommand)]
+enum Command {
+    /// Keep running, polling usage and publishing changes to MQTT
+    Daemon {
+        /// Minutes between polls
+        #[arg(short, long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+    /// Store the days missing from the local usage database
+    Sync {
+        /// First day to sync
+        #[arg(long)]
+        from: NaiveDate,
+
+        /// Last day to sync
+        #[arg(long, default_value_t = yesterday())]
+        to: NaiveDate,
+
+        /// Database file, defaults to usage.sqlite3 in the XDG data directory
+        #[arg(long)]
+        database: Option<PathBuf>,
+    },
+}
+
+// Cache passphrase, the session is stored in clear text when unset
+fn get_cache_passphrase() -> Option<Secret> {
+    env::var("HO_CACHE_PASSPHRASE")
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(Secret::from)
+}
+
+fn answer_challenge(challenge: &Challenge) -> Result<ChallengeAnswer> {
+    match challenge {
+        Challenge::Mfa(mfa) => {
+            let prompt = match (mfa.kind, &mfa.destination) {
+                (MfaKind::Sms, Some(destination)) => format!("Code sent to {destination}"),
+                (MfaKind::Sms, None) => "SMS code".to_string(),
+                (MfaKind::SoftwareToken, _) => "Authenticator app code".to_string(),
+            };
+
+            let code = Input::<String>::new().with_prompt(prompt).interact_text()?;
+            Ok(ChallengeAnswer::MfaCode(code.into()))
+        }
+        Challenge::NewPasswordRequired {
+            required_attributes,
+        } => {
+            eprintln!("A new password is required");
+            let password = Password::new()
+                .with_prompt("New password")
+                .with_confirmation("Repeat new password", "Passwords don't match")
+                .interact()?;
+
+            let mut attributes = HashMap::new();
+            for name in required_attributes {
+                let value = Input::<String>::new().with_prompt(name).interact_text()?;
+                attributes.insert(name.clone(), value);
+            }
+
+            Ok(ChallengeAnswer::NewPassword {
+                password: password.into(),
+                attributes,
+            })
+        }
+    }
+}
+
+fn build_client(args: &UserArgs) -> Result<HoClient> {
+    let mut builder =
+        HoClient::builder().user_agent(concat!("hydroottawa/", env!("CARGO_PKG_VERSION")));
+
+    if let Some(proxy) = &args.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(api_url) = &args.api_url {
+        builder = builder.api_url(api_url);
+    }
+    if let Some(cognito_endpoint) = &args.cognito_endpoint {
+        builder = builder.cognito_endpoint(cognito_endpoint);
+    }
+
+    Ok(builder.build()?)
+}
+
+// The password options, falling back to the systemd credential, the
+// environment and the prompt when none is given
+fn password_providers(args: &UserArgs) -> Vec<PasswordProvider> {
+    let explicit = if let Some(command) = &args.password_command {
+        Some(PasswordProvider::Command(command.clone()))
+    } else if let Some(path) = &args.password_file {
+        Some(PasswordProvider::File(path.clone()))
+    } else if args.password_stdin {
+        Some(PasswordProvider::Stdin)
     } else {
-        let prompt = format!("Password for {username}");
-        let password = Password::new().with_prompt(prompt).interact()?;
-        Ok(password)
+        None
+    };
+
+    credentials::providers(explicit)
+}
+
+async fn login(
+    client: &HoClient,
+    username: &str,
+    providers: &[PasswordProvider],
+    device: Option<HoDevice>,
+) -> Result<HoAuth> {
+    let password = credentials::password(providers, username)?;
+
+    let mut step =
+        HoAuth::start_login_with_device(client, username, password.expose(), device).await?;
+
+    let auth = loop {
+        match step {
+            LoginStep::Authenticated(auth) => break auth,
+            LoginStep::ChallengeRequired(pending) => {
+                let answer = answer_challenge(pending.challenge())?;
+                step = pending.respond(answer).await?;
+            }
+        }
+    };
+    eprintln!("Authentication successful!");
+    Ok(auth)
+}
+
+// Resumes the cached session, logging in with the password when it's
+// missing or rejected
+async fn authenticate(
+    client: &HoClient,
+    api: &HoApi,
+    store: Option<&TokenStore>,
+    args: &UserArgs,
+) -> Result<(HoAuth, HoProfile)> {
+    if args.replay.is_some() {
+        let auth = HoAuth::resume(client, HoSession::replayed());
+        let profile = api.profile(&auth).await?;
+        return Ok((auth, profile));
     }
+
+    let cached = match store {
+        Some(store) if !args.no_cache => store.load().unwrap_or_else(|e| {
+            warn!("Ignoring cached session: {e}");
+            None
+        }),
+        _ => None,
+    };
+
+    // A remembered device spares the MFA code on the next login
+    let device = cached.as_ref().and_then(|session| session.device.clone());
+
+    if let Some(session) = cached {
+        let auth = HoAuth::resume(client, session);
+
+        // Only a rejected session needs the password, not a network error
+        match api.profile(&auth).await {
+            Ok(profile) => return Ok((auth, profile)),
+            Err(e @ (ApiError::TokenExpired | ApiError::InvalidCredentials(_))) => {
+                info!("Cached session rejected, logging in again: {e}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let auth = login(client, &args.username, &password_providers(args), device).await?;
+    let profile = api.profile(&auth).await?;
+    Ok((auth, profile))
+}
+
+// Connection options for --mqtt, if given
+fn mqtt_broker(args: &UserArgs) -> Result<Option<MqttOptions>> {
+    let Some(server) = &args.mqtt else {
+        return Ok(None);
+    };
+
+    let tls = MqttTls {
+        ca: args.mqtt_ca.clone(),
+        client_cert: args.mqtt_cert.clone(),
+        client_key: args.mqtt_key.clone(),
+    };
+    let client_id = args
+        .mqtt_client_id
+        .clone()
+        .unwrap_or_else(default_client_id);
+    mqtt_options(server, &tls, &client_id).map(Some)
+}
+
+// A changed portal still works, but the fields may need updating
+fn warn_drift(api: &HoApi) {
+    let drift = api.drift();
+    if !drift.is_empty() {
+        warn!("The API response changed: {drift}");
+    }
+}
+
+// A reversed range fails before logging in
+fn check_date_ranges(args: &UserArgs) -> Result<()> {
+    if let Some(from) = args.from
+        && from > args.to
+    {
+        anyhow::bail!("--from {from} is after --to {}", args.to);
+    }
+    if let Some(Command::Sync { from, to, .. }) = &args.command
+        && from > to
+    {
+        anyhow::bail!("sync --from {from} is after --to {to}");
+    }
+    Ok(())
+}
+
+// Replayed sessions are placeholders, never cache them. Without a state
+// directory the run goes on without a cache.
+fn token_store(args: &UserArgs) -> Option<TokenStore> {
+    if args.replay.is_some() {
+        return None;
+    }
+
+    TokenStore::new(&args.username, get_cache_passphrase())
+        .inspect_err(|e| warn!("Not caching the session: {e}"))
+        .ok()
+}
+
+async fn save_session(store: Option<&TokenStore>, auth: &HoAuth) {
+    let Some(store) = store else {
+        return;
+    };
+
+    if let Err(e) = store.save(&auth.session().await) {
+        warn!("Unable to cache the session: {e}");
+    }
+}
+
+async fn sync(
+    source: &impl UsageSource,
+    profile: &HoProfile,
+    from: &NaiveDate,
+    to: &NaiveDate,
+    database: Option<PathBuf>,
+) -> Result<()> {
+    let mut db = match database {
+        Some(path) => UsageStore::open(&path)?,
+        None => UsageStore::open_default()?,
+    };
+
+    let account_id = &profile.account_information.account_id;
+    let (synced, failed) = db.sync(source, account_id, from, to).await?;
+
+    println!("Synced {synced} days");
+
+    if failed > 0 {
+        anyhow::bail!("{failed} days failed to sync");
+    }
+    Ok(())
 }
 
 #[tokio::main]
@@ -69,21 +407,105 @@ async fn main() -> Result<()> {
         .with_log_level(log_level)
         .start();
 
-    let password = get_password(&args.username)?;
+    check_date_ranges(&args)?;
+
+    // Bad broker settings fail before logging in
+    let mqttoptions = mqtt_broker(&args)?;
+    let topics = MqttTopics {
+        base_topic: args.mqtt_base_topic.clone(),
+        discovery_prefix: args.mqtt_discovery_prefix.clone(),
+    };
 
-    let auth = HoAuth::new(&args.username, &password).await?;
-    println!("Authentication successful!");
+    let store = token_store(&args);
+    let replay = args.replay.is_some();
 
-    let api = HoApi::new(false);
+    let client = build_client(&args)?;
+    let recording = match (&args.record, &args.replay) {
+        (Some(dir), _) => Recording::Record(dir.clone()),
+        (_, Some(dir)) => Recording::Replay(dir.clone()),
+        (None, None) => Recording::Off,
+    };
+    let api = HoApi::with_client(client.clone())
+        .with_recording(recording)
+        .with_retry_policy(RetryPolicy {
+            max_retries: args.retries,
+            ..RetryPolicy::default()
+        });
 
-    let profile = api.profile(&auth).await?;
-    let usage = api.hourly(&auth, &args.date).await?;
+    let (auth, profile) = authenticate(&client, &api, store.as_ref(), &args).await?;
+    if let Ok(claims) = auth.claims().await
+        && let Some(expires_at) = claims.expires_at()
+    {
+        info!(
+            "Session for {} expires at {expires_at}",
+            claims.username().unwrap_or(&args.username)
+        );
+    }
+    let source = LiveSource::new(&api, &auth);
+
+    match args.command {
+        Some(Command::Daemon { interval }) => {
+            let Some(mqttoptions) = mqttoptions else {
+                anyhow::bail!("The daemon needs an MQTT server (--mqtt)");
+            };
+            if replay {
+                anyhow::bail!("The daemon can't replay recordings");
+            }
+
+            let interval = Duration::from_secs(interval.saturating_mul(60));
+            let mut daemon = Daemon::new(&source, profile, interval).with_topics(topics);
+            if let Some(store) = &store {
+                daemon = daemon.with_session_cache(store, &auth);
+            }
+            return daemon.run(mqttoptions).await;
+        }
+        Some(Command::Sync { from, to, database }) => {
+            let res = sync(&source, &profile, &from, &to, database).await;
+            warn_drift(&api);
+
+            save_session(store.as_ref(), &auth).await;
+            return res;
+        }
+        None => {}
+    }
+
+    if let Some(from) = args.from {
+        let days = from.iter_days().take_while(|day| *day <= args.to).collect();
+        let usages = source.hourly_days(days).await;
+        warn_drift(&api);
+
+        save_session(store.as_ref(), &auth).await;
+
+        let mut failed = 0usize;
+        let total = usages.len();
+        let usages: Vec<_> = usages
+            .into_iter()
+            .filter_map(|(day, usage)| {
+                usage
+                    .inspect_err(|e| {
+                        error!("Unable to get the usage for {day}: {e}");
+                        failed = failed.saturating_add(1);
+                    })
+                    .ok()
+            })
+            .collect();
+
+        print_usage(args.output, &profile, &usages)?;
+
+        if failed > 0 {
+            anyhow::bail!("{failed} of {total} days failed");
+        }
+        return Ok(());
+    }
+
+    let usage = source.hourly(&args.date).await?;
+    warn_drift(&api);
+
+    save_session(store.as_ref(), &auth).await;
 
-    if let Some(mqtt_server) = args.mqtt {
-        mqtt_publish(mqtt_server, &profile, &usage).await
+    if let Some(mqttoptions) = mqttoptions {
+        mqtt_publish(mqttoptions, &topics, &profile, &usage).await
     } else {
-        println!("{}", ProfileDisplay(&profile));
-        println!("{}", UsageDisplay(&usage));
-        Ok(())
+        print_usage(args.output, &profile, &[usage])
     }
 }
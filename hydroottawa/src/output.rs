@@ -0,0 +1,87 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use hydroottawa_api::{
+    datetime::HoDateTime,
+    types::{HoHourlyUsage, HoInterval, HoProfile, RateBand},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::io::{self, Write};
+
+use crate::display::{ProfileDisplay, UsageDisplay};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable summary and interval table
+    #[default]
+    Table,
+    /// Profile and usage as a single JSON document
+    Json,
+    /// One row per hourly interval
+    Csv,
+    /// One JSON object per hourly interval
+    Ndjson,
+}
+
+// Every CSV row has the same columns, fields the portal adds to the
+// intervals only show up in the JSON formats
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CsvRow<'a> {
+    start_date_time: &'a HoDateTime,
+    end_date_time: &'a HoDateTime,
+    rate_band: &'a RateBand,
+    hourly_usage: f64,
+    hourly_cost: f64,
+}
+
+impl<'a> From<&'a HoInterval> for CsvRow<'a> {
+    fn from(interval: &'a HoInterval) -> Self {
+        Self {
+            start_date_time: &interval.start_date_time,
+            end_date_time: &interval.end_date_time,
+            rate_band: &interval.rate_band,
+            hourly_usage: interval.hourly_usage,
+            hourly_cost: interval.hourly_cost,
+        }
+    }
+}
+
+/// Writes `profile` and the usage of each day to stdout
+pub fn print_usage(
+    format: OutputFormat,
+    profile: &HoProfile,
+    usages: &[HoHourlyUsage],
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+
+    match format {
+        OutputFormat::Table => {
+            writeln!(out, "{}", ProfileDisplay(profile))?;
+            for usage in usages {
+                writeln!(out, "{}", UsageDisplay(usage))?;
+            }
+        }
+        OutputFormat::Json => {
+            // Always a list so single days and ranges parse the same
+            let doc = json!({ "profile": profile, "usage": usages });
+            serde_json::to_writer_pretty(&mut out, &doc)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            for interval in usages.iter().flat_map(|u| &u.intervals) {
+                writer.serialize(CsvRow::from(interval))?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Ndjson => {
+            for interval in usages.iter().flat_map(|u| &u.intervals) {
+                serde_json::to_writer(&mut out, interval)?;
+                writeln!(out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
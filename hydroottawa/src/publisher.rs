@@ -0,0 +1,210 @@
+//! One-off MQTT connection that confirms every message it publishes
+
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::Duration,
+};
+use tokio::time::timeout;
+
+/// How long `Publisher::publish` waits for every acknowledgement
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Message published at least once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+/// Outcome of `Publisher::publish`, topics in the order they were given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublishReport {
+    /// Acknowledged by the broker
+    pub delivered: Vec<String>,
+    /// Never sent or never acknowledged
+    pub failed: Vec<String>,
+    /// Connection error or timeout that stopped the publishing
+    pub cause: Option<String>,
+}
+
+impl PublishReport {
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for PublishReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.delivered.len().saturating_add(self.failed.len());
+        write!(
+            f,
+            "{} of {total} MQTT messages delivered",
+            self.delivered.len()
+        )?;
+        if !self.failed.is_empty() {
+            write!(f, ", unacknowledged: {}", self.failed.join(", "))?;
+        }
+        if let Some(cause) = &self.cause {
+            write!(f, " ({cause})")?;
+        }
+        Ok(())
+    }
+}
+
+// Matches packet IDs to messages, rumqttc sends requests in order so each
+// new ID belongs to the oldest message not sent yet
+struct Tracker {
+    queued: VecDeque<usize>,
+    inflight: HashMap<u16, usize>,
+    acked: Vec<bool>,
+    connected: bool,
+}
+
+impl Tracker {
+    fn new(len: usize) -> Self {
+        Self {
+            queued: (0..len).collect(),
+            inflight: HashMap::new(),
+            acked: vec![false; len],
+            connected: false,
+        }
+    }
+
+    fn sent(&mut self, pkid: u16) {
+        // Known IDs are retransmissions after a reconnect
+        if !self.inflight.contains_key(&pkid)
+            && let Some(index) = self.queued.pop_front()
+        {
+            self.inflight.insert(pkid, index);
+        }
+    }
+
+    fn acked(&mut self, pkid: u16) {
+        if let Some(index) = self.inflight.remove(&pkid)
+            && let Some(acked) = self.acked.get_mut(index)
+        {
+            *acked = true;
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.queued.is_empty() && self.inflight.is_empty()
+    }
+}
+
+async fn track(eventloop: &mut EventLoop, tracker: &mut Tracker) -> Result<(), ConnectionError> {
+    while !tracker.is_done() {
+        match eventloop.poll().await? {
+            Event::Incoming(Packet::ConnAck(_)) => {
+                info!("Connected to MQTT broker");
+                tracker.connected = true;
+            }
+            Event::Outgoing(Outgoing::Publish(pkid)) => tracker.sent(pkid),
+            Event::Incoming(Packet::PubAck(ack)) => {
+                debug!("Publish {} acknowledged", ack.pkid);
+                tracker.acked(ack.pkid);
+            }
+            event => debug!("MQTT event: {event:?}"),
+        }
+    }
+    Ok(())
+}
+
+async fn disconnect(client: &AsyncClient, eventloop: &mut EventLoop) {
+    let disconnected = async {
+        client.disconnect().await?;
+        while !matches!(
+            eventloop.poll().await?,
+            Event::Outgoing(Outgoing::Disconnect)
+        ) {}
+        anyhow::Ok(())
+    };
+
+    match timeout(DISCONNECT_TIMEOUT, disconnected).await {
+        Ok(Ok(())) => debug!("Disconnected from MQTT broker"),
+        Ok(Err(e)) => warn!("Unable to disconnect from the MQTT broker: {e}"),
+        Err(_) => warn!("Timed out disconnecting from the MQTT broker"),
+    }
+}
+
+/// Publishes a batch of messages and waits until the broker acknowledged
+/// each of them
+pub struct Publisher {
+    mqttoptions: MqttOptions,
+    timeout: Duration,
+}
+
+impl Publisher {
+    #[must_use]
+    pub fn new(mqttoptions: MqttOptions) -> Self {
+        Self {
+            mqttoptions,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Time allowed for connecting and getting every acknowledgement
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Publishes `messages` in order, then disconnects
+    pub async fn publish(self, messages: Vec<Message>) -> PublishReport {
+        // Room for every message and the disconnect, so queueing never waits
+        let (client, mut eventloop) =
+            AsyncClient::new(self.mqttoptions, messages.len().saturating_add(1));
+
+        let mut tracker = Tracker::new(messages.len());
+        let mut cause = None;
+
+        for message in &messages {
+            debug!("Publishing to {}", message.topic);
+            if let Err(e) = client.try_publish(
+                &message.topic,
+                QoS::AtLeastOnce,
+                message.retain,
+                message.payload.clone(),
+            ) {
+                cause = Some(e.to_string());
+                break;
+            }
+        }
+
+        if cause.is_none() {
+            match timeout(self.timeout, track(&mut eventloop, &mut tracker)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    cause = Some(format!("connection error: {e}"));
+                    tracker.connected = false;
+                }
+                Err(_) => {
+                    cause = Some(format!("timed out after {}s", self.timeout.as_secs()));
+                }
+            }
+        }
+
+        // A broken connection has nothing left to close
+        if tracker.connected {
+            disconnect(&client, &mut eventloop).await;
+        }
+
+        let (delivered, failed) = messages
+            .into_iter()
+            .zip(tracker.acked)
+            .partition::<Vec<_>, _>(|(_, acked)| *acked);
+
+        PublishReport {
+            delivered: delivered.into_iter().map(|(m, _)| m.topic).collect(),
+            failed: failed.into_iter().map(|(m, _)| m.topic).collect(),
+            cause,
+        }
+    }
+}
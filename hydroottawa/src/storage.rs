@@ -0,0 +1,224 @@
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDate;
+use hydroottawa_api::{datetime::hours_in_day, source::UsageSource, types::HoHourlyUsage};
+use log::{debug, error, info};
+use rusqlite::{Connection, params};
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::xdg;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS intervals (
+    account_id TEXT NOT NULL,
+    start_date_time TEXT NOT NULL,
+    end_date_time TEXT NOT NULL,
+    rate_band TEXT NOT NULL,
+    hourly_usage REAL NOT NULL,
+    hourly_cost REAL NOT NULL,
+    PRIMARY KEY (account_id, start_date_time)
+);
+
+CREATE TABLE IF NOT EXISTS summaries (
+    account_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    actual_date TEXT NOT NULL,
+    rate_plan TEXT NOT NULL,
+    billing_period_start_date TEXT NOT NULL,
+    billing_period_end_date TEXT NOT NULL,
+    total_usage REAL NOT NULL,
+    total_cost REAL NOT NULL,
+    hourly_average_usage REAL NOT NULL,
+    hourly_average_cost REAL NOT NULL,
+    total_off_peak_usage REAL NOT NULL,
+    total_off_peak_cost REAL NOT NULL,
+    total_mid_peak_usage REAL NOT NULL,
+    total_mid_peak_cost REAL NOT NULL,
+    total_on_peak_usage REAL NOT NULL,
+    total_on_peak_cost REAL NOT NULL,
+    total_ulo_usage REAL NOT NULL,
+    total_ulo_cost REAL NOT NULL,
+    number_of_hours INTEGER NOT NULL,
+    PRIMARY KEY (account_id, date)
+);
+";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Local `SQLite` history of the hourly usage
+pub struct UsageStore {
+    conn: Connection,
+}
+
+impl UsageStore {
+    /// Database under the XDG data directory
+    pub fn open_default() -> Result<Self> {
+        let dir = xdg::data_dir()?;
+        fs::create_dir_all(&dir)?;
+
+        Self::open(&dir.join("usage.sqlite3"))
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn =
+            Connection::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+        conn.execute_batch(SCHEMA)?;
+
+        debug!("Opened usage database {}", path.display());
+        Ok(Self { conn })
+    }
+
+    /// Days from `start` to `end` inclusive without a stored summary, or
+    /// with fewer intervals than the day has hours because the portal was
+    /// still posting it
+    pub fn missing_days(
+        &self,
+        account_id: &str,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> Result<Vec<NaiveDate>> {
+        // Interval starts are local RFC 3339 times, so they begin with the date
+        let mut stmt = self.conn.prepare(
+            "SELECT s.date, COUNT(i.start_date_time) FROM summaries s
+             LEFT JOIN intervals i
+                ON i.account_id = s.account_id AND substr(i.start_date_time, 1, 10) = s.date
+             WHERE s.account_id = ?1 AND s.date BETWEEN ?2 AND ?3
+             GROUP BY s.date",
+        )?;
+
+        let stored = stmt
+            .query_map(
+                params![
+                    account_id,
+                    start.format(DATE_FORMAT).to_string(),
+                    end.format(DATE_FORMAT).to_string()
+                ],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?)),
+            )?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+
+        let missing = start
+            .iter_days()
+            .take_while(|day| day <= end)
+            .filter(|day| {
+                let intervals = stored.get(&day.format(DATE_FORMAT).to_string());
+                intervals.is_none_or(|intervals| Some(*intervals) < hours_in_day(day))
+            })
+            .collect();
+
+        Ok(missing)
+    }
+
+    /// Inserts or replaces the intervals and summary of `date`
+    pub fn upsert(
+        &mut self,
+        account_id: &str,
+        date: &NaiveDate,
+        usage: &HoHourlyUsage,
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO intervals (account_id, start_date_time, end_date_time, rate_band, hourly_usage, hourly_cost)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (account_id, start_date_time) DO UPDATE SET
+                    end_date_time = excluded.end_date_time,
+                    rate_band = excluded.rate_band,
+                    hourly_usage = excluded.hourly_usage,
+                    hourly_cost = excluded.hourly_cost",
+            )?;
+
+            for interval in &usage.intervals {
+                stmt.execute(params![
+                    account_id,
+                    interval.start_date_time.to_rfc3339(),
+                    interval.end_date_time.to_rfc3339(),
+                    interval.rate_band.to_string(),
+                    interval.hourly_usage,
+                    interval.hourly_cost,
+                ])?;
+            }
+        }
+
+        let summary = &usage.summary;
+        tx.execute(
+            "INSERT OR REPLACE INTO summaries (
+                account_id, date, actual_date, rate_plan, billing_period_start_date, billing_period_end_date,
+                total_usage, total_cost, hourly_average_usage, hourly_average_cost,
+                total_off_peak_usage, total_off_peak_cost, total_mid_peak_usage, total_mid_peak_cost,
+                total_on_peak_usage, total_on_peak_cost, total_ulo_usage, total_ulo_cost, number_of_hours
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+            params![
+                account_id,
+                date.format(DATE_FORMAT).to_string(),
+                summary.actual_date.format(DATE_FORMAT).to_string(),
+                summary.rate_plan.to_string(),
+                // Empty when the portal leaves the billing period out
+                summary
+                    .billing_period_start_date
+                    .map(|date| date.to_rfc3339())
+                    .unwrap_or_default(),
+                summary
+                    .billing_period_end_date
+                    .map(|date| date.to_rfc3339())
+                    .unwrap_or_default(),
+                summary.total_usage,
+                summary.total_cost,
+                summary.hourly_average_usage,
+                summary.hourly_average_cost,
+                summary.total_off_peak_usage,
+                summary.total_off_peak_cost,
+                summary.total_mid_peak_usage,
+                summary.total_mid_peak_cost,
+                summary.total_on_peak_usage,
+                summary.total_on_peak_cost,
+                summary.total_ulo_usage,
+                summary.total_ulo_cost,
+                summary.number_of_hours,
+            ],
+        )?;
+
+        tx.commit()?;
+
+        debug!(
+            "Stored {} intervals for {account_id} on {date}",
+            usage.intervals.len()
+        );
+        Ok(())
+    }
+
+    /// Fetches and stores the days from `from` to `to` missing for
+    /// `account_id`, returns how many were stored and how many failed
+    pub async fn sync(
+        &mut self,
+        source: &impl UsageSource,
+        account_id: &str,
+        from: &NaiveDate,
+        to: &NaiveDate,
+    ) -> Result<(usize, usize)> {
+        if from > to {
+            bail!("{from} is after {to}");
+        }
+
+        let missing = self.missing_days(account_id, from, to)?;
+        info!("{} days missing from {from} to {to}", missing.len());
+
+        let mut synced = 0usize;
+        let mut failed = 0usize;
+
+        for (day, usage) in source.hourly_days(missing).await {
+            match usage {
+                Ok(usage) => {
+                    self.upsert(account_id, &day, &usage)?;
+                    synced = synced.saturating_add(1);
+                }
+                Err(e) => {
+                    error!("Unable to get the usage for {day}: {e}");
+                    failed = failed.saturating_add(1);
+                }
+            }
+        }
+
+        Ok((synced, failed))
+    }
+}
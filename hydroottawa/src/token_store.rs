@@ -0,0 +1,205 @@
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use hydroottawa_api::{auth::HoSession, secret::Secret};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroize;
+
+use crate::xdg;
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+enum StoredSession {
+    Plain {
+        session: HoSession,
+    },
+    Encrypted {
+        salt: String,
+        nonce: String,
+        ciphertext: String,
+    },
+}
+
+/// On-disk cache of the session tokens for one username
+pub struct TokenStore {
+    path: PathBuf,
+    passphrase: Option<Secret>,
+}
+
+// Keep usernames (usually e-mail addresses) safe to use as a file name
+fn file_name(username: &str) -> String {
+    let name: String = username
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '@' | '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{name}.json")
+}
+
+fn derive_key(passphrase: &Secret, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+
+    Argon2::default()
+        .hash_password_into(passphrase.expose().as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Unable to derive the cache key: {e}"))?;
+
+    Ok(key)
+}
+
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options
+        .open(&tmp_path)
+        .with_context(|| format!("Unable to create {}", tmp_path.display()))?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp_path, path).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+impl TokenStore {
+    /// Store for `username` under the XDG state directory
+    pub fn new(username: &str, passphrase: Option<Secret>) -> Result<Self> {
+        let path = xdg::state_dir()?.join(file_name(username));
+
+        Ok(Self::with_path(path, passphrase))
+    }
+
+    #[must_use]
+    pub fn with_path(path: PathBuf, passphrase: Option<Secret>) -> Self {
+        Self { path, passphrase }
+    }
+
+    /// Cached session, `None` if nothing was stored yet
+    pub fn load(&self) -> Result<Option<HoSession>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!("No cached session at {}", self.path.display());
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Unable to read {}", self.path.display()));
+            }
+        };
+
+        let stored: StoredSession = serde_json::from_slice(&data)?;
+
+        let session = match stored {
+            StoredSession::Plain { session } => session,
+            StoredSession::Encrypted {
+                salt,
+                nonce,
+                ciphertext,
+            } => {
+                let passphrase = self
+                    .passphrase
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Cached session is encrypted but no passphrase set"))?;
+
+                let key = derive_key(passphrase, &BASE64.decode(salt)?)?;
+                let nonce = BASE64.decode(nonce)?;
+                // `Nonce::from_slice` panics on any other length
+                if nonce.len() != NONCE_LEN {
+                    bail!("Invalid nonce in the cached session");
+                }
+                let ciphertext = BASE64.decode(ciphertext)?;
+
+                let mut plaintext = ChaCha20Poly1305::new(&key)
+                    .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+                    .map_err(|_| anyhow!("Unable to decrypt the cached session"))?;
+
+                let session = serde_json::from_slice(&plaintext);
+                plaintext.zeroize();
+                session?
+            }
+        };
+
+        info!("Loaded cached session from {}", self.path.display());
+        Ok(Some(session))
+    }
+
+    // Whether the cache on disk is encrypted, unreadable ones are overwritten
+    fn is_encrypted(&self) -> bool {
+        fs::read(&self.path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .is_some_and(|stored| matches!(stored, StoredSession::Encrypted { .. }))
+    }
+
+    /// Writes `session`, encrypted if there is a passphrase. Without one an
+    /// encrypted cache is left alone rather than replaced with clear text.
+    pub fn save(&self, session: &HoSession) -> Result<()> {
+        if self.passphrase.is_none() && self.is_encrypted() {
+            bail!(
+                "{} is encrypted but no passphrase set, not replacing it",
+                self.path.display()
+            );
+        }
+
+        let stored = if let Some(passphrase) = &self.passphrase {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+
+            let key = derive_key(passphrase, &salt)?;
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let mut plaintext = serde_json::to_vec(session)?;
+
+            let ciphertext = ChaCha20Poly1305::new(&key).encrypt(&nonce, plaintext.as_slice());
+            plaintext.zeroize();
+            let ciphertext = ciphertext.map_err(|_| anyhow!("Unable to encrypt the session"))?;
+
+            StoredSession::Encrypted {
+                salt: BASE64.encode(salt),
+                nonce: BASE64.encode(nonce),
+                ciphertext: BASE64.encode(ciphertext),
+            }
+        } else {
+            StoredSession::Plain {
+                session: session.clone(),
+            }
+        };
+
+        if let Some(dir) = self.path.parent() {
+            let mut builder = fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            builder.mode(0o700);
+            builder.create(dir)?;
+        }
+
+        write_private(&self.path, &serde_json::to_vec_pretty(&stored)?)?;
+
+        debug!("Saved session to {}", self.path.display());
+        Ok(())
+    }
+}
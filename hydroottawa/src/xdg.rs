@@ -0,0 +1,32 @@
+use anyhow::{Result, anyhow};
+use std::{env, path::PathBuf};
+
+// `var` if set, falling back to `fallback` under $HOME
+fn base_dir(var: &str, fallback: &[&str]) -> Result<PathBuf> {
+    if let Some(dir) = env::var_os(var).filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let home = env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set"))?;
+    Ok(fallback.iter().fold(PathBuf::from(home), |p, c| p.join(c)))
+}
+
+/// `$XDG_STATE_HOME/hydroottawa`, `~/.local/state/hydroottawa` when unset
+/// and the systemd `$STATE_DIRECTORY` without a home directory
+pub fn state_dir() -> Result<PathBuf> {
+    match base_dir("XDG_STATE_HOME", &[".local", "state"]) {
+        Ok(dir) => Ok(dir.join("hydroottawa")),
+        Err(e) => {
+            // systemd separates several `StateDirectory=` with colons
+            let dirs = env::var("STATE_DIRECTORY").map_err(|_| e)?;
+            let dir = dirs.split(':').next().filter(|d| !d.is_empty());
+            dir.map(PathBuf::from)
+                .ok_or_else(|| anyhow!("STATE_DIRECTORY is empty"))
+        }
+    }
+}
+
+/// `$XDG_DATA_HOME/hydroottawa`, `~/.local/share/hydroottawa` when unset
+pub fn data_dir() -> Result<PathBuf> {
+    Ok(base_dir("XDG_DATA_HOME", &[".local", "share"])?.join("hydroottawa"))
+}
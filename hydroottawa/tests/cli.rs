@@ -0,0 +1,382 @@
+use hydroottawa_mock::{ACCOUNT_ID, MockServer, PASSWORD, Stats, USERNAME};
+use serde_json::Value;
+use std::{path::Path, process::Output, process::Stdio};
+use tempfile::TempDir;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+// The CLI against `server` with its state and data kept under `home`
+fn cli(server: &MockServer, home: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_hydroottawa"));
+    command
+        .args(["--api-url", &server.api_url()])
+        .args(["--cognito-endpoint", &server.cognito_endpoint()])
+        .args(["--username", USERNAME])
+        .args(args)
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env_remove("HO_PASSWORD")
+        .env_remove("HO_CACHE_PASSPHRASE")
+        .env_remove("CREDENTIALS_DIRECTORY")
+        .stdin(Stdio::null());
+    command
+}
+
+// Runs the CLI with `password` in `HO_PASSWORD`
+async fn run(
+    server: &MockServer,
+    home: &Path,
+    password: Option<&str>,
+    args: &[&str],
+) -> std::io::Result<Output> {
+    let mut command = cli(server, home, args);
+
+    if let Some(password) = password {
+        command.env("HO_PASSWORD", password);
+    }
+
+    command.output().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prints_json_usage() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &["--date", "2025-12-31", "--output", "json"],
+    )
+    .await?;
+    assert!(output.status.success(), "{output:?}");
+
+    let doc: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        doc["profile"]["accountInformation"]["accountId"],
+        ACCOUNT_ID
+    );
+    assert_eq!(doc["usage"][0]["summary"]["actualDate"], "2025-12-31");
+    assert_eq!(
+        doc["usage"][0]["intervals"].as_array().map(Vec::len),
+        Some(24)
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn passes_new_interval_fields_through_json() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    server.patch_intervals("evUsage", Some(serde_json::json!(0.5)));
+
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &["--date", "2025-12-31", "--output", "json"],
+    )
+    .await?;
+    assert!(output.status.success(), "{output:?}");
+    let doc: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(doc["usage"][0]["intervals"][0]["evUsage"], 0.5);
+
+    // CSV keeps its columns
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &["--date", "2025-12-31", "--output", "csv"],
+    )
+    .await?;
+    assert!(output.status.success(), "{output:?}");
+    let csv = String::from_utf8(output.stdout)?;
+    assert_eq!(
+        csv.lines().next(),
+        Some("startDateTime,endDateTime,rateBand,hourlyUsage,hourlyCost")
+    );
+    assert_eq!(csv.lines().count(), 25);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reuses_cached_session() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let args = ["--date", "2025-12-31", "--output", "ndjson"];
+
+    let first = run(&server, home.path(), Some(PASSWORD), &args).await?;
+    assert!(first.status.success(), "{first:?}");
+
+    // No password and no terminal to prompt on, only the cache can work
+    let second = run(&server, home.path(), None, &args).await?;
+    assert!(second.status.success(), "{second:?}");
+    assert_eq!(first.stdout, second.stdout);
+    assert_eq!(server.stats().logins, 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keeps_cached_session_on_server_errors() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let args = [
+        "--date",
+        "2025-12-31",
+        "--output",
+        "ndjson",
+        "--retries",
+        "0",
+    ];
+
+    let first = run(&server, home.path(), Some(PASSWORD), &args).await?;
+    assert!(first.status.success(), "{first:?}");
+
+    // A failing portal isn't a rejected session, so no password login
+    server.fail_api(503, 1);
+    let second = run(&server, home.path(), Some(PASSWORD), &args).await?;
+    assert!(!second.status.success());
+    assert_eq!(server.stats().logins, 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn caches_session_without_home() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let args = ["--date", "2025-12-31", "--output", "json"];
+
+    // Nowhere to cache the session, the run still succeeds
+    let output = cli(&server, home.path(), &args)
+        .env("HO_PASSWORD", PASSWORD)
+        .env_remove("XDG_STATE_HOME")
+        .env_remove("STATE_DIRECTORY")
+        .env_remove("HOME")
+        .output()
+        .await?;
+    assert!(output.status.success(), "{output:?}");
+
+    // A systemd service without a home directory uses its state directory
+    let state = home.path().join("service");
+    let output = cli(&server, home.path(), &args)
+        .env("HO_PASSWORD", PASSWORD)
+        .env("STATE_DIRECTORY", &state)
+        .env_remove("XDG_STATE_HOME")
+        .env_remove("HOME")
+        .output()
+        .await?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(state.join(format!("{USERNAME}.json")).exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_wrong_password() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+
+    let output = run(&server, home.path(), Some("hunter2"), &["--output", "json"]).await?;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_range_with_reversed_dates() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &["--from", "2026-01-02", "--to", "2025-12-30"],
+    )
+    .await?;
+    assert!(!output.status.success());
+    assert_eq!(server.stats().logins, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_sync_with_reversed_dates() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let database = home.path().join("usage.sqlite3");
+    let database = database.to_str().ok_or("non UTF-8 path")?;
+
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &[
+            "sync",
+            "--from",
+            "2026-01-05",
+            "--to",
+            "2026-01-01",
+            "--database",
+            database,
+        ],
+    )
+    .await?;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(server.stats().logins, 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_zero_interval() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+
+    let output = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &["daemon", "--interval", "0"],
+    )
+    .await?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--interval"));
+    assert_eq!(server.stats(), Stats::default());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn syncs_missing_days() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let database = home.path().join("usage.sqlite3");
+    let database = database.to_str().ok_or("non UTF-8 path")?;
+    let args = [
+        "sync",
+        "--from",
+        "2025-12-29",
+        "--to",
+        "2026-01-01",
+        "--database",
+        database,
+    ];
+
+    let output = run(&server, home.path(), Some(PASSWORD), &args).await?;
+    assert!(output.status.success(), "{output:?}");
+    let fetched = server.stats().api_requests;
+
+    // Everything is stored, only the profile is fetched again
+    let output = run(&server, home.path(), Some(PASSWORD), &args).await?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(server.stats().api_requests, fetched.saturating_add(1));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_recording() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let fixtures = home.path().join("fixtures");
+    let fixtures = fixtures.to_str().ok_or("non UTF-8 path")?;
+    let args = ["--date", "2025-12-31", "--output", "json"];
+
+    let recorded = run(
+        &server,
+        home.path(),
+        Some(PASSWORD),
+        &[&args[..], &["--record", fixtures]].concat(),
+    )
+    .await?;
+    assert!(recorded.status.success(), "{recorded:?}");
+    let logins = server.stats().logins;
+    drop(server);
+
+    // The server is gone, only the fixtures can answer
+    // Nor is there anywhere to cache the session
+    let server = MockServer::start().await?;
+    let replayed = cli(
+        &server,
+        home.path(),
+        &[&args[..], &["--replay", fixtures]].concat(),
+    )
+    .env_remove("XDG_STATE_HOME")
+    .env_remove("STATE_DIRECTORY")
+    .env_remove("HOME")
+    .output()
+    .await?;
+    assert!(replayed.status.success(), "{replayed:?}");
+    assert_eq!(recorded.stdout, replayed.stdout);
+    assert_eq!(server.stats(), Stats::default());
+    assert_eq!(logins, 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_password_from_providers() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+    let args = ["--no-cache", "--output", "json"];
+
+    let file = home.path().join("password");
+    std::fs::write(&file, format!("{PASSWORD}\n"))?;
+    let file = file.to_str().ok_or("non UTF-8 path")?;
+
+    let output = cli(
+        &server,
+        home.path(),
+        &[&args[..], &["--password-file", file]].concat(),
+    )
+    .output()
+    .await?;
+    assert!(output.status.success());
+
+    let command = format!("cat '{file}'");
+    let output = cli(
+        &server,
+        home.path(),
+        &[&args[..], &["--password-command", &command]].concat(),
+    )
+    .output()
+    .await?;
+    assert!(output.status.success());
+
+    let output = cli(&server, home.path(), &args)
+        .env("CREDENTIALS_DIRECTORY", home.path())
+        .output()
+        .await?;
+    assert!(output.status.success());
+
+    let mut child = cli(
+        &server,
+        home.path(),
+        &[&args[..], &["--password-stdin"]].concat(),
+    )
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or("no stdin")?
+        .write_all(format!("{PASSWORD}\n").as_bytes())
+        .await?;
+    assert!(child.wait_with_output().await?.status.success());
+
+    assert_eq!(server.stats().logins, 4);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_when_password_command_fails() -> TestResult {
+    let server = MockServer::start().await?;
+    let home = TempDir::new()?;
+
+    let output = cli(&server, home.path(), &["--password-command", "exit 1"])
+        .env("HO_PASSWORD", PASSWORD)
+        .output()
+        .await?;
+    assert!(!output.status.success());
+    assert_eq!(server.stats().logins, 0);
+    Ok(())
+}
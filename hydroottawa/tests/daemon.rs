@@ -0,0 +1,214 @@
+use chrono::{Local, NaiveDate};
+use hydroottawa::{
+    daemon::Daemon,
+    mqtt_pub::{MqttTls, MqttTopics, OFFLINE, ONLINE, mqtt_options, state_payload},
+};
+use hydroottawa_api::{
+    error::{Error as ApiError, Result as ApiResult},
+    source::{MemorySource, UsageSource},
+    types::{HoHourlyUsage, HoProfile},
+};
+use hydroottawa_mock::{MockBroker, Published, hourly_fixture, profile_fixture};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+// Far shorter than the 30 second backoff after a failed poll
+const INTERVAL: Duration = Duration::from_millis(20);
+
+// `MemorySource` counting the usage requests
+struct CountingSource {
+    source: MemorySource,
+    polls: AtomicUsize,
+}
+
+impl CountingSource {
+    fn new(source: MemorySource) -> Self {
+        Self {
+            source,
+            polls: AtomicUsize::new(0),
+        }
+    }
+
+    fn polls(&self) -> usize {
+        self.polls.load(Ordering::SeqCst)
+    }
+}
+
+impl UsageSource for CountingSource {
+    async fn profile(&self) -> ApiResult<HoProfile> {
+        self.source.profile().await
+    }
+
+    async fn hourly(&self, date: &NaiveDate) -> ApiResult<HoHourlyUsage> {
+        self.polls.fetch_add(1, Ordering::SeqCst);
+        self.source.hourly(date).await
+    }
+}
+
+// Source whose session Cognito no longer accepts
+struct ExpiredSource(HoProfile);
+
+impl UsageSource for ExpiredSource {
+    async fn profile(&self) -> ApiResult<HoProfile> {
+        Ok(self.0.clone())
+    }
+
+    async fn hourly(&self, _date: &NaiveDate) -> ApiResult<HoHourlyUsage> {
+        Err(ApiError::TokenExpired)
+    }
+}
+
+// Profile and the usage of the day the daemon polls, the previous one
+fn fixtures() -> Result<(HoProfile, HoHourlyUsage), Box<dyn std::error::Error>> {
+    let today = Local::now().date_naive();
+    let date = today.pred_opt().ok_or("invalid date")?;
+
+    let profile = serde_json::from_value(profile_fixture())?;
+    let usage = serde_json::from_value(hourly_fixture(date))?;
+    Ok((profile, usage))
+}
+
+async fn wait_until(mut done: impl FnMut() -> bool) {
+    for _ in 0..500 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn published_to(broker: &MockBroker, topic: &str) -> Vec<Published> {
+    broker
+        .published()
+        .into_iter()
+        .filter(|message| message.topic == topic)
+        .collect()
+}
+
+#[tokio::test]
+async fn publishes_polled_usage() -> TestResult {
+    let broker = MockBroker::start().await?;
+    let options = mqtt_options(&broker.address(), &MqttTls::default(), "test")?;
+    let (profile, usage) = fixtures()?;
+    let source = MemorySource::new(profile.clone()).with_hourly(usage.clone());
+
+    let topics = MqttTopics::default();
+    let state_topic = topics.state_topic(&profile.account_information.account_id);
+
+    let daemon = Daemon::new(&source, profile, Duration::from_hours(1)).with_topics(topics);
+    let published = || published_to(&broker, &state_topic);
+    daemon
+        .run_until(options, wait_until(|| !published().is_empty()))
+        .await?;
+
+    let published = published();
+    let state = published.first().ok_or("no state published")?;
+    assert_eq!(state.payload, state_payload(&usage).to_string().as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn skips_unchanged_usage() -> TestResult {
+    let broker = MockBroker::start().await?;
+    let options = mqtt_options(&broker.address(), &MqttTls::default(), "test")?;
+    let (profile, usage) = fixtures()?;
+    let source = CountingSource::new(MemorySource::new(profile.clone()).with_hourly(usage));
+
+    let state_topic = MqttTopics::default().state_topic(&profile.account_information.account_id);
+
+    Daemon::new(&source, profile, INTERVAL)
+        .run_until(options, wait_until(|| source.polls() >= 3))
+        .await?;
+
+    // Polled again and again, published once
+    assert!(source.polls() >= 3);
+    assert_eq!(published_to(&broker, &state_topic).len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn backs_off_after_failed_poll() -> TestResult {
+    let broker = MockBroker::start().await?;
+    let options = mqtt_options(&broker.address(), &MqttTls::default(), "test")?;
+    let (profile, _) = fixtures()?;
+
+    // No usage posted yet, every poll fails
+    let source = CountingSource::new(MemorySource::new(profile.clone()));
+    let state_topic = MqttTopics::default().state_topic(&profile.account_information.account_id);
+
+    let shutdown = async {
+        wait_until(|| source.polls() >= 1).await;
+        // Several intervals, but still within the backoff
+        tokio::time::sleep(INTERVAL.saturating_mul(10)).await;
+    };
+    Daemon::new(&source, profile, INTERVAL)
+        .run_until(options, shutdown)
+        .await?;
+
+    assert_eq!(source.polls(), 1);
+    assert!(published_to(&broker, &state_topic).is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_once_session_expires() -> TestResult {
+    let broker = MockBroker::start().await?;
+    let options = mqtt_options(&broker.address(), &MqttTls::default(), "test")?;
+    let (profile, _) = fixtures()?;
+    let source = ExpiredSource(profile.clone());
+
+    // Never shuts down on its own, only the expired session ends it
+    let result = Daemon::new(&source, profile, INTERVAL)
+        .run_until(options, std::future::pending())
+        .await;
+
+    let error = result.err().ok_or("daemon kept polling")?;
+    assert!(matches!(
+        error.downcast_ref::<ApiError>(),
+        Some(ApiError::TokenExpired)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn goes_offline_on_shutdown() -> TestResult {
+    let broker = MockBroker::start().await?;
+    let options = mqtt_options(&broker.address(), &MqttTls::default(), "test")?;
+    let (profile, usage) = fixtures()?;
+    let source = MemorySource::new(profile.clone()).with_hourly(usage);
+
+    let topics = MqttTopics::default();
+    let account_id = &profile.account_information.account_id;
+    let state_topic = topics.state_topic(account_id);
+    let availability_topic = topics.availability_topic(account_id);
+
+    Daemon::new(&source, profile.clone(), Duration::from_hours(1))
+        .run_until(
+            options,
+            wait_until(|| !published_to(&broker, &state_topic).is_empty()),
+        )
+        .await?;
+    wait_until(|| broker.disconnects() > 0).await;
+
+    // Online while connected, offline as the last word before a clean
+    // disconnect
+    let availability: Vec<_> = published_to(&broker, &availability_topic)
+        .into_iter()
+        .map(|message| (message.payload, message.retain))
+        .collect();
+    assert_eq!(
+        availability,
+        [
+            (ONLINE.as_bytes().to_vec(), true),
+            (OFFLINE.as_bytes().to_vec(), true)
+        ]
+    );
+    let last = broker.published().pop().ok_or("nothing published")?;
+    assert_eq!(last.topic, availability_topic);
+    assert_eq!(broker.disconnects(), 1);
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use chrono::NaiveDate;
+use hydroottawa::storage::UsageStore;
+use hydroottawa_api::{
+    source::MemorySource,
+    types::{HoHourlyUsage, HoProfile},
+};
+use hydroottawa_mock::{ACCOUNT_ID, hourly_fixture, profile_fixture};
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+#[tokio::test]
+async fn syncs_from_any_source() -> TestResult {
+    let profile: HoProfile = serde_json::from_value(profile_fixture())?;
+    let from = NaiveDate::from_ymd_opt(2025, 12, 29).ok_or("invalid date")?;
+    let to = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+
+    // No usage for the first day yet
+    let mut source = MemorySource::new(profile);
+    for day in from.iter_days().skip(1).take(2) {
+        let usage: HoHourlyUsage = serde_json::from_value(hourly_fixture(day))?;
+        source = source.with_hourly(usage);
+    }
+
+    let dir = tempfile::tempdir()?;
+    let mut db = UsageStore::open(&dir.path().join("usage.sqlite3"))?;
+
+    assert_eq!(db.sync(&source, ACCOUNT_ID, &from, &to).await?, (2, 1));
+    assert_eq!(db.missing_days(ACCOUNT_ID, &from, &to)?, [from]);
+
+    // Stored days aren't fetched again
+    assert_eq!(db.sync(&source, ACCOUNT_ID, &from, &to).await?, (0, 1));
+    Ok(())
+}
+
+#[tokio::test]
+async fn refetches_partial_days() -> TestResult {
+    let profile: HoProfile = serde_json::from_value(profile_fixture())?;
+    let day = NaiveDate::from_ymd_opt(2025, 12, 31).ok_or("invalid date")?;
+
+    // Only the morning was posted so far
+    let mut partial: HoHourlyUsage = serde_json::from_value(hourly_fixture(day))?;
+    partial.intervals.truncate(10);
+    let source = MemorySource::new(profile.clone()).with_hourly(partial);
+
+    let dir = tempfile::tempdir()?;
+    let mut db = UsageStore::open(&dir.path().join("usage.sqlite3"))?;
+
+    assert_eq!(db.sync(&source, ACCOUNT_ID, &day, &day).await?, (1, 0));
+    assert_eq!(db.missing_days(ACCOUNT_ID, &day, &day)?, [day]);
+
+    let full: HoHourlyUsage = serde_json::from_value(hourly_fixture(day))?;
+    let source = MemorySource::new(profile).with_hourly(full);
+    assert_eq!(db.sync(&source, ACCOUNT_ID, &day, &day).await?, (1, 0));
+    assert!(db.missing_days(ACCOUNT_ID, &day, &day)?.is_empty());
+    Ok(())
+}
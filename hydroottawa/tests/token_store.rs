@@ -0,0 +1,105 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::Utc;
+use hydroottawa::token_store::TokenStore;
+use hydroottawa_api::auth::HoSession;
+use serde_json::json;
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+#[test]
+fn rejects_corrupt_cache() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("session.json");
+    let stored = json!({
+        "format": "encrypted",
+        "salt": BASE64.encode([0u8; 16]),
+        "nonce": BASE64.encode([0u8; 5]),
+        "ciphertext": BASE64.encode([0u8; 32]),
+    });
+    std::fs::write(&path, stored.to_string())?;
+
+    let store = TokenStore::with_path(path, Some("passphrase".into()));
+    assert!(store.load().is_err());
+    Ok(())
+}
+
+fn session() -> HoSession {
+    HoSession {
+        jwt_token: "jwt".into(),
+        id_token: "id".into(),
+        access_token: "access".into(),
+        refresh_token: "refresh".into(),
+        expires_at: Utc::now(),
+        device: None,
+    }
+}
+
+#[test]
+fn round_trips_encrypted_session() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("session.json");
+    let saved = session();
+
+    let store = TokenStore::with_path(path.clone(), Some("passphrase".into()));
+    store.save(&saved)?;
+
+    // Nothing readable on disk
+    let stored = std::fs::read_to_string(&path)?;
+    assert!(!stored.contains("refresh"));
+
+    let loaded = store.load()?.ok_or("no session loaded")?;
+    assert_eq!(loaded.refresh_token.expose(), "refresh");
+    assert_eq!(loaded.jwt_token.expose(), "jwt");
+    assert_eq!(loaded.expires_at, saved.expires_at);
+    Ok(())
+}
+
+#[test]
+fn rejects_wrong_passphrase() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("session.json");
+
+    TokenStore::with_path(path.clone(), Some("passphrase".into())).save(&session())?;
+
+    let store = TokenStore::with_path(path.clone(), Some("wrong".into()));
+    assert!(store.load().is_err());
+    let store = TokenStore::with_path(path, None);
+    assert!(store.load().is_err());
+    Ok(())
+}
+
+#[test]
+fn keeps_encrypted_cache_without_passphrase() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("session.json");
+
+    let encrypted = TokenStore::with_path(path.clone(), Some("passphrase".into()));
+    encrypted.save(&session())?;
+    let before = std::fs::read(&path)?;
+
+    // A run without the passphrase mustn't write the tokens in clear text
+    let store = TokenStore::with_path(path.clone(), None);
+    assert!(store.save(&session()).is_err());
+    assert_eq!(std::fs::read(&path)?, before);
+    assert!(encrypted.load()?.is_some());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn creates_private_file() -> TestResult {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("state").join("session.json");
+
+    TokenStore::with_path(path.clone(), None).save(&session())?;
+
+    let mode = std::fs::metadata(&path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let mode = std::fs::metadata(dir.path().join("state"))?
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o700);
+    Ok(())
+}